@@ -0,0 +1,246 @@
+use crate::coord::Dir;
+use crate::rules::MAX_FIELD_SIZE;
+use crate::time::frames;
+
+// Terminals send no key-up events, so a held key is detected from the
+// repeated presses the terminal sends. The first repeat comes after the
+// terminal's own delay, which is 660 milliseconds at most on common setups.
+const MAX_REPEAT_DELAY: u64 = 700;
+
+// Milliseconds without a repeated press after which the key is regarded as released.
+const RELEASE_TIMEOUT: u64 = 100;
+
+// Shifts enough to move a tetromino across any field at once.
+const INSTANT_SHIFT: usize = MAX_FIELD_SIZE;
+
+struct Held {
+    dir: Dir,
+    // Frames since the first press.
+    frames: u64,
+    // Frames since the last press.
+    idle: u64,
+    // Frames since auto repeat started, if it did.
+    repeat: Option<u64>,
+}
+
+// AutoShift keeps moving a tetromino while a direction key is held down.
+// After the delayed auto shift (DAS) the tetromino moves by one cell every
+// auto repeat rate (ARR) frames, or straight to the wall if ARR is zero.
+// When the terminal reports key releases they end the hold instead of timeouts.
+pub struct AutoShift {
+    das: u64,
+    arr: u64,
+    release_events: bool,
+    max_repeat_delay: u64,
+    release_timeout: u64,
+    held: Option<Held>,
+}
+
+impl AutoShift {
+    // DAS and ARR are given in milliseconds.
+    pub fn new(das: u64, arr: u64, release_events: bool) -> Self {
+        AutoShift {
+            das: frames(das),
+            arr: frames(arr),
+            release_events,
+            max_repeat_delay: frames(MAX_REPEAT_DELAY),
+            release_timeout: frames(RELEASE_TIMEOUT),
+            held: None,
+        }
+    }
+
+    // Returns whether the press should move the tetromino by itself.
+    // Presses while auto repeating only keep the key held.
+    pub fn press(&mut self, dir: Dir) -> bool {
+        if let Some(held) = self.held.as_mut() {
+            let detect = !self.release_events;
+            if detect && held.dir == dir && held.idle <= self.max_repeat_delay {
+                let rapid = held.idle <= self.release_timeout;
+                held.idle = 0;
+                if held.repeat.is_some() {
+                    return false;
+                }
+                if rapid && held.frames >= self.das {
+                    held.repeat = Some(0);
+                    return false;
+                }
+                return true;
+            }
+        }
+
+        self.held = Some(Held {
+            dir,
+            frames: 0,
+            idle: 0,
+            repeat: None,
+        });
+        true
+    }
+
+    pub fn release(&mut self, dir: Dir) {
+        if self.held.as_ref().is_some_and(|held| held.dir == dir) {
+            self.held = None;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.held = None;
+    }
+
+    // Advances a frame and returns the shifts to apply in it.
+    pub fn update(&mut self) -> Option<(Dir, usize)> {
+        let held = self.held.as_mut()?;
+        if !self.release_events {
+            let timeout = match held.repeat {
+                Some(_) => self.release_timeout,
+                None => self.max_repeat_delay,
+            };
+            if held.idle > timeout {
+                self.held = None;
+                return None;
+            }
+        }
+
+        held.frames += 1;
+        held.idle += 1;
+        if self.release_events && held.repeat.is_none() && held.frames > self.das {
+            held.repeat = Some(0);
+        }
+        let repeat = held.repeat?;
+        held.repeat = Some(repeat + 1);
+
+        if self.arr == 0 {
+            Some((held.dir, INSTANT_SHIFT))
+        } else if repeat % self.arr == 0 {
+            Some((held.dir, 1))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the number of cells moved in each frame, pressing the key
+    // at the given frames.
+    fn simulate(das: u64, arr: u64, presses: &[u64], n_frames: u64) -> Vec<usize> {
+        let mut shift = AutoShift::new(das, arr, false);
+        (0..n_frames)
+            .map(|f| {
+                let mut moved = 0;
+                if presses.contains(&f) && shift.press(Dir::Right) {
+                    moved += 1;
+                }
+                if let Some((_, n)) = shift.update() {
+                    moved += n;
+                }
+                moved
+            })
+            .collect()
+    }
+
+    fn moved_frames(moves: &[usize]) -> Vec<u64> {
+        (0..moves.len() as u64)
+            .filter(|&f| moves[f as usize] > 0)
+            .collect()
+    }
+
+    #[test]
+    fn auto_shift() {
+        struct Case<'a> {
+            title: &'a str,
+            das: u64,
+            arr: u64,
+            presses: &'a [u64],
+            n_frames: u64,
+            moved: &'a [u64],
+        }
+        let cases = [
+            Case {
+                title: "single tap",
+                das: 150,
+                arr: 50,
+                presses: &[0],
+                n_frames: 60,
+                moved: &[0],
+            },
+            Case {
+                title: "rapid taps before DAS",
+                das: 150,
+                arr: 50,
+                presses: &[0, 3, 6],
+                n_frames: 60,
+                moved: &[0, 3, 6],
+            },
+            Case {
+                title: "slow taps",
+                das: 150,
+                arr: 50,
+                presses: &[0, 20, 40],
+                n_frames: 60,
+                moved: &[0, 20, 40],
+            },
+            Case {
+                title: "held until released",
+                das: 150,
+                arr: 50,
+                presses: &[0, 30, 32, 34, 36, 38, 40],
+                n_frames: 60,
+                moved: &[0, 30, 32, 35, 38, 41, 44],
+            },
+            Case {
+                title: "new press after a long pause",
+                das: 150,
+                arr: 50,
+                presses: &[0, 50, 52],
+                n_frames: 60,
+                moved: &[0, 50, 52],
+            },
+        ];
+
+        for Case {
+            title,
+            das,
+            arr,
+            presses,
+            n_frames,
+            moved,
+        } in cases.iter()
+        {
+            let moves = simulate(*das, *arr, presses, *n_frames);
+            assert_eq!(moved_frames(&moves), moved.to_vec(), "{}", title);
+        }
+    }
+
+    #[test]
+    fn zero_arr_shifts_to_the_wall() {
+        let moves = simulate(150, 0, &[0, 30, 32], 40);
+        assert_eq!(moves[32], INSTANT_SHIFT);
+    }
+
+    #[test]
+    fn other_direction_cancels_repeat() {
+        let mut shift = AutoShift::new(0, 50, false);
+        shift.press(Dir::Right);
+        shift.update();
+        shift.press(Dir::Right);
+        assert_eq!(shift.update(), Some((Dir::Right, 1)));
+
+        assert!(shift.press(Dir::Left));
+        assert_eq!(shift.update(), None);
+    }
+
+    #[test]
+    fn held_until_release_event() {
+        let mut shift = AutoShift::new(150, 50, true);
+        assert!(shift.press(Dir::Left));
+        let moves: Vec<_> = (0..16).map(|_| shift.update()).collect();
+        let moved: Vec<_> = (0..16).filter(|&f| moves[f].is_some()).collect();
+        assert_eq!(moved, vec![9, 12, 15]);
+
+        shift.release(Dir::Left);
+        assert_eq!(shift.update(), None);
+    }
+}
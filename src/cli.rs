@@ -1,23 +1,48 @@
-use crate::inputs::KeyConverter;
+pub use crate::config::Config;
 use failure::{Fail, Fallible};
 use getopts::Options;
-
-pub struct Config {
-    key: KeyConverter,
-}
-
-impl Config {
-    pub fn key(&self) -> KeyConverter {
-        self.key
-    }
-}
+use std::path::{Path, PathBuf};
 
 pub enum CliParsed {
     Help(String),
     Run(Config),
+    Replay(Config, PathBuf),
+    Verify(PathBuf),
+    Scores,
 }
 
+const USAGE: &str = "Usage: textris [options]
+       textris replay [options] FILE
+       textris verify FILE
+       textris scores";
+
 pub fn parse_args(args: &[String]) -> Fallible<CliParsed> {
+    parse_args_with(args, |path| match path {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    })
+}
+
+// Options are given by the name of the setting in the config file
+// so that both are parsed the same way.
+const SETTING_OPTS: [&str; 11] = [
+    "key",
+    "randomizer",
+    "spawn",
+    "width",
+    "height",
+    "level",
+    "lock-delay",
+    "das",
+    "arr",
+    "preview",
+    "theme",
+];
+
+fn parse_args_with<F>(args: &[String], load_config: F) -> Fallible<CliParsed>
+where
+    F: FnOnce(Option<&Path>) -> Fallible<Config>,
+{
     let _program = &args[0];
     let args = &args[1..];
 
@@ -27,30 +52,275 @@ pub fn parse_args(args: &[String]) -> Fallible<CliParsed> {
         .map_err(|e| e.context("failed to parse arguments"))?;
 
     if m.opt_present("h") {
-        let usage = opts.usage("");
+        let usage = opts.usage(USAGE);
         return Ok(CliParsed::Help(usage));
     }
 
-    let key = if let Some(key) = m.opt_str("key") {
-        match key.as_str() {
-            "vim" => KeyConverter::Vim,
-            "normal" => KeyConverter::Normal,
-            key => return Err(format_err!("invalid key type: {}", key)),
-        }
-    } else {
-        KeyConverter::Normal
+    let replay = match m.free.iter().map(|s| s.as_str()).collect::<Vec<_>>()[..] {
+        [] => None,
+        ["replay", file] => Some(PathBuf::from(file)),
+        ["scores"] => return Ok(CliParsed::Scores),
+        ["verify", file] => return Ok(CliParsed::Verify(PathBuf::from(file))),
+        ["replay"] | ["verify"] => return Err(format_err!("replay file is not given")),
+        [command, ..] => return Err(format_err!("unknown command: {}", command)),
     };
 
-    Ok(CliParsed::Run(Config { key }))
+    let config_path = m.opt_str("config").map(PathBuf::from);
+    let mut config = load_config(config_path.as_deref())?;
+
+    for name in SETTING_OPTS.iter() {
+        if let Some(value) = m.opt_str(name) {
+            config
+                .set(name, &value)
+                .map_err(|e| format_err!("{}", e).context(format!("invalid --{}", name)))?;
+        }
+    }
+    if m.opt_present("no-ghost") {
+        config
+            .set("ghost", "false")
+            .map_err(|e| format_err!("{}", e))?;
+    }
+    if let Some(seed) = m
+        .opt_get::<u64>("seed")
+        .map_err(|e| e.context("invalid seed"))?
+    {
+        config.set_seed(seed);
+    }
+    config.check()?;
+
+    match replay {
+        Some(file) => Ok(CliParsed::Replay(config, file)),
+        None => Ok(CliParsed::Run(config)),
+    }
 }
 
 fn define_opts(opts: &mut Options) -> &mut Options {
     opts.optflag("h", "help", "print this help message");
+    opts.optopt(
+        "c",
+        "config",
+        "read settings from the file (default is ~/.config/textris/config)",
+        "FILE",
+    );
     opts.optopt(
         "k",
         "key",
         "choose key mode (default is normal)",
         "vim | normal",
     );
+    opts.optopt(
+        "r",
+        "randomizer",
+        "choose how next pieces are generated (default is random)",
+        "random | bag7 | bag14 | history",
+    );
+    opts.optopt(
+        "s",
+        "spawn",
+        "choose where new pieces appear (default is guideline)",
+        "guideline | chaos",
+    );
+    opts.optopt(
+        "",
+        "width",
+        "set the field width (default is 16)",
+        "COLUMNS",
+    );
+    opts.optopt("", "height", "set the field height (default is 16)", "ROWS");
+    opts.optopt(
+        "l",
+        "level",
+        "set the starting level (default is 1)",
+        "LEVEL",
+    );
+    opts.optopt(
+        "",
+        "lock-delay",
+        "set how long landed pieces wait before locking (default is 500)",
+        "MILLISECONDS",
+    );
+    opts.optopt(
+        "",
+        "das",
+        "set how long a held direction waits before auto shifting (default is 167)",
+        "MILLISECONDS",
+    );
+    opts.optopt(
+        "",
+        "arr",
+        "set the interval between auto shifts, 0 to shift instantly (default is 33)",
+        "MILLISECONDS",
+    );
+    opts.optopt(
+        "p",
+        "preview",
+        "set how many next pieces are shown (default is 3)",
+        "COUNT",
+    );
+    opts.optopt(
+        "",
+        "seed",
+        "set the random seed to play the same game again",
+        "NUMBER",
+    );
+    opts.optopt(
+        "",
+        "theme",
+        "choose the colors (default is default)",
+        "default | mono",
+    );
+    opts.optflag(
+        "",
+        "no-ghost",
+        "hide the landing position of the falling piece",
+    );
     opts
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Fallible<CliParsed> {
+        let args: Vec<String> = ["textris"]
+            .iter()
+            .chain(args)
+            .map(|s| s.to_string())
+            .collect();
+        parse_args_with(&args, |_| Ok(Config::default()))
+    }
+
+    #[test]
+    fn parse_field_size() {
+        struct Case<'a> {
+            args: &'a [&'a str],
+            size: Option<(usize, usize)>,
+        }
+        let cases = [
+            Case {
+                args: &[],
+                size: Some((16, 16)),
+            },
+            Case {
+                args: &["--width", "10", "--height", "20"],
+                size: Some((10, 20)),
+            },
+            Case {
+                args: &["--width", "3"],
+                size: None,
+            },
+            Case {
+                args: &["--height", "200"],
+                size: None,
+            },
+            Case {
+                args: &["--width", "wide"],
+                size: None,
+            },
+        ];
+
+        for Case { args, size } in cases.iter() {
+            let parsed = match parse(args) {
+                Ok(CliParsed::Run(conf)) => Some((conf.rules().width, conf.rules().height)),
+                _ => None,
+            };
+            assert_eq!(parsed, *size, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parse_preview() {
+        struct Case<'a> {
+            args: &'a [&'a str],
+            preview: Option<usize>,
+        }
+        let cases = [
+            Case {
+                args: &[],
+                preview: Some(3),
+            },
+            Case {
+                args: &["--preview", "6"],
+                preview: Some(6),
+            },
+            Case {
+                args: &["-p", "1"],
+                preview: Some(1),
+            },
+            Case {
+                args: &["--preview", "0"],
+                preview: None,
+            },
+            Case {
+                args: &["--preview", "7"],
+                preview: None,
+            },
+        ];
+
+        for Case { args, preview } in cases.iter() {
+            let parsed = match parse(args) {
+                Ok(CliParsed::Run(conf)) => Some(conf.rules().preview),
+                _ => None,
+            };
+            assert_eq!(parsed, *preview, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        struct Case<'a> {
+            args: &'a [&'a str],
+            replay: Option<Option<&'a str>>,
+        }
+        let cases = [
+            Case {
+                args: &[],
+                replay: Some(None),
+            },
+            Case {
+                args: &["replay", "a.replay"],
+                replay: Some(Some("a.replay")),
+            },
+            Case {
+                args: &["replay", "-k", "vim", "a.replay"],
+                replay: Some(Some("a.replay")),
+            },
+            Case {
+                args: &["replay"],
+                replay: None,
+            },
+            Case {
+                args: &["verify"],
+                replay: None,
+            },
+            Case {
+                args: &["play"],
+                replay: None,
+            },
+        ];
+
+        for Case { args, replay } in cases.iter() {
+            let parsed = match parse(args) {
+                Ok(CliParsed::Run(_)) => Some(None),
+                Ok(CliParsed::Replay(_, path)) => Some(Some(path)),
+                _ => None,
+            };
+            let expected = replay.map(|r| r.map(PathBuf::from));
+            assert_eq!(parsed, expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn options_override_config() {
+        let args: Vec<String> = ["textris", "--width", "12", "--das", "90"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let parsed = parse_args_with(&args, |_| "width = 10\nheight = 20\ndas = 100".parse());
+        let rules = match parsed {
+            Ok(CliParsed::Run(conf)) => conf.rules(),
+            _ => panic!("failed to parse {:?}", args),
+        };
+        assert_eq!((rules.width, rules.height, rules.das), (12, 20, 90));
+    }
+}
@@ -20,6 +20,16 @@ macro_rules! define_color {
                     Color::$var(tmc::$var)
                 }
             )*
+
+            // Colors are named like `light-blue`.
+            pub fn from_name(name: &str) -> Option<Color> {
+                match name.replace('-', "_").as_str() {
+                    $(
+                        stringify!($method) => Some(Color::$method()),
+                    )*
+                    _ => None,
+                }
+            }
         }
 
         impl tmc::Color for Color {
@@ -0,0 +1,312 @@
+use crate::color::Color;
+use crate::inputs::{self, KeyConverter, KeyMap, Order};
+use crate::paths;
+use crate::randomizer::RandomizerKind;
+use crate::rules::{Rules, SpawnPolicy};
+use crate::theme::Theme;
+use failure::{Fail, Fallible};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+// Config holds the settings given by the config file and the command line.
+//
+// The config file has a setting per line like below. Command line options
+// of the same names override them.
+//
+//   # Comments start with '#'.
+//   key = vim
+//   bind-hold = c tab
+//   width = 10
+//   das = 133
+//   theme = mono
+//   color-t = magenta
+#[derive(Clone)]
+pub struct Config {
+    keys: KeyMap,
+    ghost: bool,
+    rules: Rules,
+    theme: Theme,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            keys: KeyMap::new(KeyConverter::Normal),
+            ghost: true,
+            rules: Rules::default(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn keys(&self) -> &KeyMap {
+        &self.keys
+    }
+
+    pub fn ghost(&self) -> bool {
+        self.ghost
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    // Loads the config file of the user. It is fine not to have one.
+    pub fn load_default() -> Fallible<Config> {
+        let path = paths::config_file()?;
+        match fs::metadata(&path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            _ => Config::load(&path),
+        }
+    }
+
+    pub fn load(path: &Path) -> Fallible<Config> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| e.context(format!("failed to read {}", path.display())))?;
+        let config = text
+            .parse()
+            .map_err(|e: failure::Error| e.context(format!("invalid config {}", path.display())))?;
+        Ok(config)
+    }
+
+    // Checks the settings which are valid only as a whole.
+    pub fn check(&self) -> Fallible<()> {
+        self.keys.check().map_err(|e| format_err!("{}", e))
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rules.seed = Some(seed);
+    }
+
+    // Changes a setting by its name, the same one as the command line option.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let mut rules = self.rules;
+        match name {
+            "key" => {
+                let preset = KeyConverter::from_name(value)
+                    .ok_or_else(|| format!("invalid key type: {}", value))?;
+                self.keys.set_preset(preset);
+            }
+            "ghost" => self.ghost = parse_value(value)?,
+            "randomizer" => {
+                rules.randomizer = RandomizerKind::from_name(value)
+                    .ok_or_else(|| format!("invalid randomizer: {}", value))?
+            }
+            "spawn" => {
+                rules.spawn = SpawnPolicy::from_name(value)
+                    .ok_or_else(|| format!("invalid spawn policy: {}", value))?
+            }
+            "width" => rules.width = parse_value(value)?,
+            "height" => rules.height = parse_value(value)?,
+            "level" => rules.start_level = parse_value(value)?,
+            "lock-delay" => rules.lock_delay = parse_value(value)?,
+            "das" => rules.das = parse_value(value)?,
+            "arr" => rules.arr = parse_value(value)?,
+            "preview" => rules.preview = parse_value(value)?,
+            "theme" => {
+                self.theme =
+                    Theme::from_name(value).ok_or_else(|| format!("invalid theme: {}", value))?
+            }
+            "field-bg" => self.theme.field_bg = parse_color(value)?,
+            _ if name.starts_with("bind-") => {
+                let order_name = &name["bind-".len()..];
+                let order = Order::from_name(order_name)
+                    .ok_or_else(|| format!("unknown order: {}", order_name))?;
+                let keys = value
+                    .split_whitespace()
+                    .map(|k| inputs::parse_key(k).ok_or_else(|| format!("unknown key: {}", k)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.keys.bind(order, &keys)?;
+            }
+            _ if name.starts_with("color-") => {
+                let color = parse_color(value)?;
+                let mut chars = name["color-".len()..].chars();
+                match (chars.next(), chars.next()) {
+                    (Some(chr), None) if self.theme.set_piece_color(chr, color) => {}
+                    _ => return Err(format!("unknown setting: {}", name)),
+                }
+            }
+            _ => return Err(format!("unknown setting: {}", name)),
+        }
+        rules.check().map_err(|e| e.to_string())?;
+        self.rules = rules;
+        Ok(())
+    }
+}
+
+impl FromStr for Config {
+    type Err = failure::Error;
+
+    fn from_str(text: &str) -> Fallible<Config> {
+        let mut config = Config::default();
+        // Lines binding keys to each order, to tell where invalid keys come from.
+        let mut bind_lines = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let err = |msg: String| format_err!("line {}: {}", i + 1, msg);
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((name, value)) => {
+                    let name = name.trim();
+                    config.set(name, value.trim()).map_err(err)?;
+                    if let Some(order) = name.strip_prefix("bind-").and_then(Order::from_name) {
+                        bind_lines.insert(order, i + 1);
+                    }
+                }
+                None => return Err(err(format!("expected 'name = value': {}", line))),
+            }
+        }
+        if let Err(e) = config.keys.check() {
+            return Err(match bind_lines.get(&e.order) {
+                Some(n) => format_err!("line {}: {}", n, e),
+                None => format_err!("{}", e),
+            });
+        }
+        Ok(config)
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value: {}", value))
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    Color::from_name(value).ok_or_else(|| format!("invalid color: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coord::Dir;
+    use termion::event::Key;
+
+    #[test]
+    fn parse_config() {
+        let text = "
+# My settings
+key = vim
+ghost = false  # I do not need it
+width = 10
+height=20
+lock-delay = 300
+das = 100
+arr = 0
+preview = 5
+randomizer = history
+spawn = chaos
+level = 3
+theme = mono
+color-t = magenta
+bind-move-left = h left
+bind-hold = tab
+";
+        let config: Config = text.parse().unwrap();
+        assert_eq!(config.keys().preset(), KeyConverter::Vim);
+        assert!(!config.ghost());
+        assert_eq!(
+            config.keys().keys(Order::Move(Dir::Left)),
+            vec![Key::Char('h'), Key::Left]
+        );
+        assert_eq!(config.keys().keys(Order::Hold), vec![Key::Char('\t')]);
+        assert_eq!(
+            config.rules(),
+            Rules {
+                width: 10,
+                height: 20,
+                lock_delay: 300,
+                das: 100,
+                arr: 0,
+                preview: 5,
+                randomizer: RandomizerKind::History,
+                spawn: SpawnPolicy::Chaos,
+                start_level: 3,
+                ..Rules::default()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_configs() {
+        struct Case<'a> {
+            text: &'a str,
+            error: &'a str,
+        }
+        let cases = [
+            Case {
+                text: "key = emacs",
+                error: "line 1: invalid key type: emacs",
+            },
+            Case {
+                text: "# comment\n\nwidth = 2",
+                error: "line 3: width must be between 4 and 100 (got 2)",
+            },
+            Case {
+                text: "das = 100\narr = fast",
+                error: "line 2: invalid value: fast",
+            },
+            Case {
+                text: "speed = 3",
+                error: "line 1: unknown setting: speed",
+            },
+            Case {
+                text: "color-q = red",
+                error: "line 1: unknown setting: color-q",
+            },
+            Case {
+                text: "field-bg = pink",
+                error: "line 1: invalid color: pink",
+            },
+            Case {
+                text: "bind-hold = c\nbind-pause = space c",
+                error: "line 2: c is already bound to hold",
+            },
+            Case {
+                text: "bind-hold = shift",
+                error: "line 1: unknown key: shift",
+            },
+            Case {
+                text: "bind-jump = j",
+                error: "line 1: unknown order: jump",
+            },
+            Case {
+                text: "bind-quit =",
+                error: "line 1: no key is bound to quit",
+            },
+            Case {
+                text: "width = 10\nbind-hold = q\nbind-pause = p",
+                error: "line 2: no key is bound to quit",
+            },
+            Case {
+                text: "bind-hold = d\n# comment\nbind-hold = d c",
+                error: "line 3: d is bound to hold, leaving no key for rotate-ccw",
+            },
+            Case {
+                text: "preview 3",
+                error: "line 1: expected 'name = value': preview 3",
+            },
+        ];
+
+        for Case { text, error } in cases.iter() {
+            match text.parse::<Config>() {
+                Ok(_) => panic!("{:?} should be invalid", text),
+                Err(err) => assert_eq!(err.to_string(), *error),
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::auto_shift::AutoShift;
+use crate::coord::Dir;
+use crate::inputs::{KeyAction, Order};
+use crate::play::Play;
+use crate::replay::{Record, Replay};
+use crate::rules::Rules;
+
+// Controller drives a play by orders and frames. Plays with the same rules,
+// including the seed, always end up the same when given the same orders
+// at the same frames.
+pub struct Controller {
+    release_events: bool,
+    play: Play,
+    auto_shift: AutoShift,
+    records: Vec<Record>,
+}
+
+impl Controller {
+    // Key releases change how held directions are detected, so they must be
+    // reported the same way for the same result.
+    pub fn new(rules: Rules, release_events: bool) -> Self {
+        Controller {
+            release_events,
+            play: Play::new(rules),
+            auto_shift: AutoShift::new(rules.das, rules.arr, release_events),
+            records: Vec::new(),
+        }
+    }
+
+    pub fn play(&self) -> &Play {
+        &self.play
+    }
+
+    // Applies an order to the play. Orders not about the play are ignored,
+    // except that pausing and opening the help forget held keys, because
+    // their releases may not reach the game.
+    pub fn apply(&mut self, order: Order, action: KeyAction) -> Result<(), ()> {
+        if is_play_order(order) {
+            self.records.push(Record {
+                frame: self.play.frame(),
+                order,
+                action,
+            });
+        }
+
+        let play = &mut self.play;
+        match order {
+            Order::Move(dir) if dir == Dir::Left || dir == Dir::Right => {
+                // Repeats of held directions are made by the auto shift.
+                match action {
+                    KeyAction::Press if self.auto_shift.press(dir) => play.slide_tetro(dir),
+                    KeyAction::Release => self.auto_shift.release(dir),
+                    _ => {}
+                }
+                Ok(())
+            }
+            _ if action == KeyAction::Release => Ok(()),
+            Order::Move(dir) => {
+                play.slide_tetro(dir);
+                Ok(())
+            }
+            Order::Rotate(rotation) => {
+                play.rotate_tetro(rotation);
+                Ok(())
+            }
+            Order::HardDrop => play.hard_drop(),
+            Order::SonicDrop => {
+                play.sonic_drop();
+                Ok(())
+            }
+            Order::Hold => play.hold_tetro(),
+            Order::Pause | Order::FocusLost | Order::Help => {
+                self.auto_shift.reset();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_replay(&self) -> Replay {
+        Replay {
+            rules: self.play.rules(),
+            release_events: self.release_events,
+            records: self.records.clone(),
+            score: self.play.score(),
+            lines: self.play.lines(),
+            frames: self.play.frame(),
+        }
+    }
+
+    // Advances the play by one frame.
+    pub fn update(&mut self) -> Result<(), ()> {
+        if let Some((dir, shifts)) = self.auto_shift.update() {
+            for _ in 0..shifts {
+                self.play.slide_tetro(dir);
+            }
+        }
+        self.play.update()
+    }
+}
+
+fn is_play_order(order: Order) -> bool {
+    match order {
+        Order::Move(_)
+        | Order::Rotate(_)
+        | Order::HardDrop
+        | Order::SonicDrop
+        | Order::Hold
+        | Order::Pause
+        | Order::FocusLost
+        | Order::Help => true,
+        Order::Select | Order::Quit => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coord::RotateDir;
+
+    // Plays until the game is over or the frames run out, giving the orders
+    // at their frames.
+    fn simulate(seed: u64, orders: &[(u64, Order, KeyAction)], n_frames: u64) -> Controller {
+        let rules = Rules {
+            seed: Some(seed),
+            ..Rules::default()
+        };
+        let mut ctrl = Controller::new(rules, false);
+        for frame in 0..n_frames {
+            for &(_, order, action) in orders.iter().filter(|(f, _, _)| *f == frame) {
+                if ctrl.apply(order, action).is_err() {
+                    return ctrl;
+                }
+            }
+            if ctrl.update().is_err() {
+                return ctrl;
+            }
+        }
+        ctrl
+    }
+
+    fn snapshot(ctrl: &Controller) -> (Vec<String>, usize, usize, u64) {
+        let play = ctrl.play();
+        let lines = play
+            .field()
+            .lines_iter()
+            .map(|line| {
+                line.iter()
+                    .map(|cell| cell.map_or('.', |b| b.chr))
+                    .collect()
+            })
+            .collect();
+        (lines, play.score(), play.lines(), play.frame())
+    }
+
+    fn falling_x(ctrl: &Controller) -> i8 {
+        let (_, coords) = ctrl.play().falling_tetro().unwrap();
+        coords.iter().map(|c| c.0).min().unwrap()
+    }
+
+    #[test]
+    fn help_forgets_held_keys() {
+        let rules = Rules {
+            seed: Some(0),
+            ..Rules::default()
+        };
+        let mut ctrl = Controller::new(rules, true);
+        ctrl.update().unwrap();
+        ctrl.apply(Order::Move(Dir::Left), KeyAction::Press)
+            .unwrap();
+        ctrl.apply(Order::Help, KeyAction::Press).unwrap();
+
+        // The release is lost in the modal, but the tetromino stays.
+        let x = falling_x(&ctrl);
+        for _ in 0..30 {
+            ctrl.update().unwrap();
+        }
+        assert_eq!(falling_x(&ctrl), x);
+    }
+
+    #[test]
+    fn same_seed_and_orders_make_same_game() {
+        use self::KeyAction::*;
+        let mut orders = Vec::new();
+        for i in 0..40 {
+            let frame = i * 30;
+            let order = match i % 5 {
+                0 => Order::Move(Dir::Left),
+                1 => Order::Rotate(RotateDir::Clockwise),
+                2 => Order::Move(Dir::Right),
+                3 => Order::Hold,
+                _ => Order::HardDrop,
+            };
+            orders.push((frame, order, Press));
+        }
+
+        let a = simulate(42, &orders, 1500);
+        let b = simulate(42, &orders, 1500);
+        assert_eq!(snapshot(&a), snapshot(&b));
+
+        let c = simulate(43, &orders, 1500);
+        assert_ne!(snapshot(&a).0, snapshot(&c).0);
+    }
+}
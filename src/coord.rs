@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign};
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Coord(pub i8, pub i8);
 
 impl Coord {
@@ -33,8 +33,9 @@ impl AddAssign for Coord {
 
 pub type Dirs = [Dir; 4];
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum Dir {
+    #[default]
     Up,
     Right,
     Down,
@@ -74,12 +75,6 @@ impl Dir {
     }
 }
 
-impl Default for Dir {
-    fn default() -> Self {
-        Dir::Up
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RotateDir {
     Clockwise,
@@ -1,31 +1,30 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
 pub struct Elapsed {
-    hours: u64,
-    minutes: u64,
-    seconds: u64,
+    millis: u64,
 }
 
 impl Elapsed {
-    pub fn new() -> Self {
-        Elapsed::default()
+    pub fn from_millis(millis: u64) -> Self {
+        Elapsed { millis }
     }
 
-    pub fn add_secs(&mut self, added: u64) {
-        let secs = self.hours * 3600 + self.minutes * 60 + self.seconds + added;
-        self.hours = secs / 3600;
-        self.minutes = (secs % 3600) / 60;
-        self.seconds = secs % 60;
+    pub fn millis(self) -> u64 {
+        self.millis
     }
 }
 
 impl fmt::Display for Elapsed {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.millis / 1000;
         write!(
             f,
-            "{:02}:{:02}:{:02}",
-            self.hours, self.minutes, self.seconds
+            "{:02}:{:02}:{:02}.{:03}",
+            secs / 3600,
+            (secs % 3600) / 60,
+            secs % 60,
+            self.millis % 1000
         )
     }
 }
@@ -36,42 +35,30 @@ mod tests {
 
     #[test]
     fn elapsed_display() {
-        let e = Elapsed {
-            hours: 7,
-            minutes: 34,
-            seconds: 52,
-        };
-        assert_eq!(format!("{}", e), "07:34:52");
-    }
-
-    #[test]
-    fn elapsed_add_secs() {
         struct Case {
-            secs: u64,
-            expected: Elapsed,
+            millis: u64,
+            text: &'static str,
         }
         let cases = [
             Case {
-                secs: 30,
-                expected: Elapsed {
-                    hours: 0,
-                    minutes: 0,
-                    seconds: 30,
-                },
+                millis: 0,
+                text: "00:00:00.000",
+            },
+            Case {
+                millis: 30_017,
+                text: "00:00:30.017",
+            },
+            Case {
+                millis: 5_001_250,
+                text: "01:23:21.250",
             },
             Case {
-                secs: 5001,
-                expected: Elapsed {
-                    hours: 1,
-                    minutes: 23,
-                    seconds: 21,
-                },
+                millis: 27_292_999,
+                text: "07:34:52.999",
             },
         ];
-        for (i, Case { secs, expected }) in cases.iter().enumerate() {
-            let mut e = Elapsed::new();
-            e.add_secs(*secs);
-            assert_eq!(e, *expected, "test[{}]", i);
+        for Case { millis, text } in cases.iter() {
+            assert_eq!(Elapsed::from_millis(*millis).to_string(), *text);
         }
     }
 }
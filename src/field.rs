@@ -7,23 +7,28 @@ use std::slice::Iter;
 type Line = Vec<Option<Block>>;
 type Cells = Vec<Line>;
 
+// Field has hidden rows above the visible ones as a vanish zone.
+// Coordinates in the vanish zone have negative y, and lines are indexed
+// from the top of the vanish zone.
 pub struct Field {
     cells: Cells,
     width: usize,
     height: usize,
+    hidden: usize,
 }
 
 fn make_line(width: usize) -> Line {
-    iter::repeat(None).take(width).collect()
+    iter::repeat_n(None, width).collect()
 }
 
 impl Field {
-    pub fn new(width: usize, height: usize) -> Self {
-        let cells = (0..height).map(|_| make_line(width)).collect();
+    pub fn new(width: usize, height: usize, hidden: usize) -> Self {
+        let cells = (0..hidden + height).map(|_| make_line(width)).collect();
         Field {
             cells,
             width,
             height,
+            hidden,
         }
     }
 
@@ -38,29 +43,13 @@ impl Field {
     pub fn is_in_range(&self, pos: Coord) -> bool {
         let w = self.width as i8;
         let h = self.height as i8;
-        0 <= pos.x() && pos.x() < w && 0 <= pos.y() && pos.y() < h
-    }
-
-    fn is_above_ceil(&self, pos: Coord) -> bool {
-        pos.y() < 0 && 0 <= pos.x() && pos.x() < self.width as i8
+        0 <= pos.x() && pos.x() < w && -(self.hidden as i8) <= pos.y() && pos.y() < h
     }
 
     pub fn is_movable(&self, coords: &[Coord]) -> bool {
         coords
             .iter()
-            .all(|&c| self.is_above_ceil(c) || self.is_in_range(c) && self[c].is_none())
-    }
-
-    pub fn is_reached(&self) -> bool {
-        self.cells[0].iter().any(|c| c.is_some())
-    }
-
-    pub fn clear_blocks(&mut self, coords: &[Coord]) {
-        for &pos in coords {
-            if self.is_in_range(pos) {
-                self[pos] = None;
-            }
-        }
+            .all(|&c| self.is_in_range(c) && self[c].is_none())
     }
 
     pub fn render_blocks(&mut self, block: Block, coords: &[Coord]) {
@@ -71,16 +60,20 @@ impl Field {
         }
     }
 
-    pub fn lines_iter(&self) -> Iter<Line> {
+    pub fn lines_iter(&self) -> Iter<'_, Line> {
         self.cells.iter()
     }
 
+    pub fn visible_lines_iter(&self) -> Iter<'_, Line> {
+        self.cells[self.hidden..].iter()
+    }
+
     pub fn get_line(&self, y: usize) -> &Line {
         &self.cells[y]
     }
 
     pub fn set_line(&mut self, y: usize, line: Line) {
-        if y < self.height {
+        if y < self.cells.len() {
             self.cells[y] = line;
         }
     }
@@ -99,13 +92,13 @@ impl Index<Coord> for Field {
             return &None;
         }
         let Coord(x, y) = index;
-        &self.cells[y as usize][x as usize]
+        &self.cells[(y + self.hidden as i8) as usize][x as usize]
     }
 }
 
 impl IndexMut<Coord> for Field {
     fn index_mut(&mut self, index: Coord) -> &mut Self::Output {
         let Coord(x, y) = index;
-        &mut self.cells[y as usize][x as usize]
+        &mut self.cells[(y + self.hidden as i8) as usize][x as usize]
     }
 }
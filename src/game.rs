@@ -1,65 +1,118 @@
 use crate::action::Action;
+use crate::controller::Controller;
 use crate::coord::{Dir, RotateDir};
-use crate::inputs::{Inputs, Order};
+use crate::inputs::{self, Inputs, KeyAction, KeyMap, Order};
 use crate::play::Play;
-use crate::screen::{Modal, Screen};
+use crate::replay::Replay;
+use crate::rules::Rules;
+use crate::scores::{self, Score, ScoreTable};
+use crate::screen::{self, Modal, Screen};
+use crate::time::FRAME;
 use failure::{Error, Fallible};
 use std::io::Write;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use termion::event::Key;
 
-const FRAME: u64 = 50;
-const TICK: u64 = 1000 / FRAME;
-const UPDATE: u64 = TICK / 2;
+// Frames to catch up at most at once, such as after the process was suspended.
+const MAX_CATCH_UP_FRAMES: u32 = 10;
+
+const REPLAY_SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
 
 pub struct Game<W: Write> {
     inputs: Inputs,
     screen: Screen<W>,
+    rules: Rules,
     help_modal: Modal,
+    paused: bool,
+    player: String,
 }
 
-fn make_help_modal(inputs: &Inputs) -> Modal {
-    let orders = [
-        (Order::Move(Dir::Left), "Move left"),
-        (Order::Move(Dir::Right), "Move right"),
-        (Order::Move(Dir::Down), "Speed up"),
-        (Order::Rotate(RotateDir::AntiClockwise), "rotate"),
-        (Order::Rotate(RotateDir::Clockwise), "rotate"),
-        (Order::Quit, "quit"),
-    ];
-    let content = orders
+// The help is made before the game starts to check it fits the terminal.
+pub fn make_help_modal(keys: &KeyMap) -> Modal {
+    let orders: Vec<_> = Order::all()
         .iter()
-        .map(|&(order, desc)| {
-            let key = inputs.bound_key(order);
-            format!("{} - {}", key_name(key), desc)
-        })
+        .filter_map(|&order| order_desc(order).map(|desc| (order, desc)))
         .collect();
-
     Modal {
         title: "HELP".to_string(),
-        content,
+        content: help_lines(keys, &orders),
         actions: vec![Action::Ok, Action::Reset, Action::Quit],
     }
 }
 
+// Returns the description of the order, or None if the player cannot give it.
+fn order_desc(order: Order) -> Option<&'static str> {
+    let desc = match order {
+        Order::Move(Dir::Left) => "Move left",
+        Order::Move(Dir::Right) => "Move right",
+        Order::Move(Dir::Down) => "Speed up",
+        Order::Rotate(RotateDir::Clockwise) => "Rotate right",
+        Order::Rotate(RotateDir::AntiClockwise) => "Rotate left",
+        Order::HardDrop => "Hard drop",
+        Order::SonicDrop => "Sonic drop",
+        Order::Hold => "Hold",
+        Order::Select => "Select",
+        Order::Pause => "Pause",
+        Order::Help => "Help",
+        Order::Quit => "Quit",
+        Order::Move(Dir::Up) | Order::FocusLost => return None,
+    };
+    Some(desc)
+}
+
+fn make_replay_help_modal(keys: &KeyMap) -> Modal {
+    let orders = [
+        (Order::Pause, "Pause"),
+        (Order::Move(Dir::Left), "Slower"),
+        (Order::Move(Dir::Right), "Faster"),
+        (Order::Move(Dir::Down), "Step a frame while paused"),
+        (Order::Quit, "Quit"),
+    ];
+    Modal {
+        title: "REPLAY HELP".to_string(),
+        content: help_lines(keys, &orders),
+        actions: vec![Action::Ok, Action::Quit],
+    }
+}
+
+// Lists every key of the orders. Orders without keys are left out.
+fn help_lines(keys: &KeyMap, orders: &[(Order, &str)]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for &(order, desc) in orders.iter() {
+        let keys = keys.keys(order);
+        if keys.is_empty() {
+            continue;
+        }
+        let mut words: Vec<_> = keys.into_iter().map(key_name).collect();
+        words.push(format!("- {}", desc));
+        lines.extend(screen::wrap_words(&words, screen::MODAL_LINE_WIDTH));
+    }
+    lines
+}
+
 fn key_name(key: Key) -> String {
     match key {
-        Key::Char(chr) => chr.to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
         Key::Left => "←".to_string(),
         Key::Right => "→".to_string(),
+        Key::Up => "↑".to_string(),
         Key::Down => "↓".to_string(),
-        _ => "".to_string(),
+        key => inputs::key_name(key),
     }
 }
 
 impl<W: Write> Game<W> {
-    pub fn new(inputs: Inputs, screen: Screen<W>) -> Self {
-        let help_modal = make_help_modal(&inputs);
+    pub fn new(inputs: Inputs, screen: Screen<W>, rules: Rules, help_modal: Modal) -> Self {
         Game {
             inputs,
             screen,
+            rules,
             help_modal,
+            paused: false,
+            player: scores::default_name(),
         }
     }
 
@@ -85,55 +138,237 @@ impl<W: Write> Game<W> {
         thread::sleep(Duration::from_millis(800));
 
         loop {
-            let next_action = self.play(Play::new())?;
+            let ctrl = Controller::new(self.rules, self.inputs.reports_release());
+            let next_action = self.play(ctrl)?;
             if next_action == Action::Quit {
                 return Ok(());
             }
         }
     }
 
-    fn play(&mut self, mut play: Play) -> Fallible<Action> {
+    // Replays a recorded game until quit.
+    pub fn replay(&mut self, replay: &Replay) -> Fallible<()> {
+        self.screen.render_title()?;
+        thread::sleep(Duration::from_millis(800));
+
+        loop {
+            let next_action = self.play_back(replay)?;
+            if next_action == Action::Quit {
+                return Ok(());
+            }
+        }
+    }
+
+    fn play_back(&mut self, replay: &Replay) -> Fallible<Action> {
+        self.screen.render_header()?;
+        let help_modal = make_replay_help_modal(self.inputs.keys());
+        let mut playback = replay.playback();
+        let mut speed = NORMAL_SPEED;
+        let mut paused = false;
+
+        let mut last = Instant::now();
+        let mut lag = Duration::ZERO;
+        loop {
+            let mut step = false;
+            match self.inputs.try_recv_order()? {
+                Some(Ok((_, KeyAction::Release))) | None => {}
+                Some(Ok((order, _))) => match order {
+                    Order::Pause => paused = !paused,
+                    Order::Move(Dir::Left) => speed = speed.saturating_sub(1),
+                    Order::Move(Dir::Right) => speed = (speed + 1).min(REPLAY_SPEEDS.len() - 1),
+                    Order::Move(Dir::Down) => step = paused,
+                    Order::Quit => return Ok(Action::Quit),
+                    Order::Help => {
+                        let action = self.screen.show_modal(&mut self.inputs, &help_modal)?;
+                        if action != Action::Ok {
+                            return Ok(action);
+                        }
+                        last = Instant::now();
+                    }
+                    _ => {}
+                },
+                Some(Err(err)) => return Err(err.into()),
+            }
+
+            let frame = FRAME.div_f64(REPLAY_SPEEDS[speed]);
+            let now = Instant::now();
+            if paused {
+                lag = Duration::ZERO;
+                if step {
+                    playback.step();
+                }
+            } else {
+                lag = (lag + (now - last)).min(frame * MAX_CATCH_UP_FRAMES);
+                while lag >= frame && !playback.is_finished() {
+                    lag -= frame;
+                    playback.step();
+                }
+            }
+            last = now;
+
+            self.screen.render(playback.play())?;
+            self.screen
+                .render_replay_status(REPLAY_SPEEDS[speed], paused)?;
+            if playback.is_finished() {
+                return self
+                    .screen
+                    .render_replay_end(&mut self.inputs, playback.play());
+            }
+
+            let next_frame = last + frame.min(FRAME).saturating_sub(lag);
+            thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    // Saves the replay of the finished game and shows the result.
+    // Replays are saved only here. Games left by quitting or resetting
+    // have no result to verify, so they are not recorded.
+    fn game_over(&mut self, ctrl: &Controller) -> Fallible<Action> {
+        // A failure to save is shown in the modal rather than ending the game.
+        let replay = ctrl.to_replay().save();
+        let rank = self.record_score(ctrl.play())?;
+        self.screen
+            .render_game_over(&mut self.inputs, ctrl.play(), &replay, &rank)
+    }
+
+    // Asks the name of the player for a new high score and saves it.
+    // Returns the rank if the score made the table, or the error of the
+    // score file which is shown to the player instead of ending the game.
+    fn record_score(&mut self, play: &Play) -> Fallible<Fallible<Option<usize>>> {
+        // Broken score files are left as they are rather than overwritten.
+        let mut table = match ScoreTable::load() {
+            Ok(table) => table,
+            Err(err) => return Ok(Err(err)),
+        };
+        let mode = self.rules.mode();
+        let rank = match table.rank(&mode, play.score()) {
+            Some(rank) => rank,
+            None => return Ok(Ok(None)),
+        };
+
+        let name = self
+            .screen
+            .render_name_entry(&mut self.inputs, play, rank, &self.player)?;
+        self.player = name.clone();
+        table.insert(Score {
+            mode,
+            name,
+            score: play.score(),
+            lines: play.lines(),
+            time: play.elapsed(),
+            date: scores::now(),
+            seed: play.seed(),
+        });
+        Ok(table.save().map(|_| Some(rank)))
+    }
+
+    fn play(&mut self, mut ctrl: Controller) -> Fallible<Action> {
         self.screen.render_header()?;
+        self.paused = false;
 
-        let interval = Duration::from_millis(FRAME);
-        let mut t = 0;
+        // The play advances by fixed frames for the time passed since the
+        // last loop, so its speed does not depend on how long rendering takes.
+        let mut last = Instant::now();
+        let mut lag = Duration::ZERO;
         loop {
-            if let Some(action) = self.handle_user_input(&mut play)? {
+            if let Some(action) = self.handle_user_input(&mut ctrl)? {
                 if action != Action::Ok {
                     return Ok(action);
                 }
+                // Time spent in a modal is not played.
+                last = Instant::now();
             }
-
-            if t % UPDATE == 0 && play.update().is_err() {
-                return self.screen.render_game_over(&mut self.inputs, &play);
+            if self.paused {
+                self.screen.render_paused(ctrl.play())?;
+                thread::sleep(FRAME);
+                last = Instant::now();
+                continue;
             }
-            if t % TICK == 0 {
-                play.tick();
+
+            let now = Instant::now();
+            lag = (lag + (now - last)).min(FRAME * MAX_CATCH_UP_FRAMES);
+            last = now;
+            while lag >= FRAME {
+                lag -= FRAME;
+                if ctrl.update().is_err() {
+                    return self.game_over(&ctrl);
+                }
             }
 
-            self.screen.render(&play)?;
-            thread::sleep(interval);
-            t += 1;
+            self.screen.render(ctrl.play())?;
+            let next_frame = last + (FRAME - lag);
+            thread::sleep(next_frame.saturating_duration_since(Instant::now()));
         }
     }
 
-    fn handle_user_input(&mut self, play: &mut Play) -> Fallible<Option<Action>> {
+    fn handle_user_input(&mut self, ctrl: &mut Controller) -> Fallible<Option<Action>> {
         match self.inputs.try_recv_order()? {
-            Some(Ok(order)) => match order {
-                Order::Move(dir) => play.slide_tetro(dir),
-                Order::Rotate(rotation) => play.rotate_tetro(rotation),
-                Order::Quit => return Ok(Some(Action::Quit)),
-                Order::Help => {
-                    return self
-                        .screen
-                        .show_modal(&mut self.inputs, &self.help_modal)
-                        .map(Some);
+            Some(Ok((order, action))) => {
+                // Only resuming and quitting are allowed while paused.
+                if self.paused {
+                    match order {
+                        Order::Pause if action == KeyAction::Press => self.paused = false,
+                        Order::Quit => return Ok(Some(Action::Quit)),
+                        _ => {}
+                    }
+                    return Ok(None);
+                }
+
+                let result = match order {
+                    Order::Quit => return Ok(Some(Action::Quit)),
+                    Order::Help if action != KeyAction::Release => {
+                        // Keys released while the modal is open are not seen.
+                        let _ = ctrl.apply(order, action);
+                        return self
+                            .screen
+                            .show_modal(&mut self.inputs, &self.help_modal)
+                            .map(Some);
+                    }
+                    Order::Pause | Order::FocusLost if action != KeyAction::Release => {
+                        self.paused = true;
+                        ctrl.apply(order, action)
+                    }
+                    _ => ctrl.apply(order, action),
+                };
+                if result.is_err() {
+                    return self.game_over(ctrl).map(Some);
                 }
-                _ => {}
-            },
+            }
             Some(Err(err)) => return Err(err.into()),
             None => {}
         };
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inputs::KeyConverter;
+
+    #[test]
+    fn help_lists_every_order() {
+        let mut keys = KeyMap::new(KeyConverter::Normal);
+        let chars = "abeghijklmno".chars();
+        keys.bind(
+            Order::Hold,
+            &chars.clone().map(Key::Ctrl).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        keys.bind(Order::Pause, &chars.map(Key::Alt).collect::<Vec<_>>())
+            .unwrap();
+
+        let help = make_help_modal(&keys);
+        assert!(help.content.len() > Order::all().len());
+        for &order in Order::all().iter() {
+            if let Some(desc) = order_desc(order) {
+                let desc = format!("- {}", desc);
+                assert!(
+                    help.content.iter().any(|l| l.ends_with(&desc)),
+                    "{}",
+                    order.name()
+                );
+            }
+        }
+    }
+}
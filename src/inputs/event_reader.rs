@@ -1,35 +1,24 @@
-use super::{KeyConverter, Order};
-use std::collections::HashMap;
-use termion::event::{Event, Key};
+use super::{KeyMap, Order, FOCUS_OUT};
+use termion::event::Event;
 
 pub struct EventReader {
-    key2order: HashMap<Key, Order>,
+    keys: KeyMap,
 }
 
 impl EventReader {
-    pub fn new(keys: KeyConverter) -> Self {
-        EventReader {
-            key2order: keys.key_to_order(),
-        }
+    pub fn new(keys: KeyMap) -> Self {
+        EventReader { keys }
     }
 
     pub fn order(&self, input: Event) -> Option<Order> {
-        if let Event::Key(key) = input {
-            self.key2order.get(&key).copied()
-        } else {
-            None
+        match input {
+            Event::Key(key) => self.keys.order(key),
+            Event::Unsupported(ref bytes) if bytes == FOCUS_OUT => Some(Order::FocusLost),
+            _ => None,
         }
     }
 
-    pub fn bound_key(&self, desired_order: Order) -> Key {
-        for (&key, &order) in self.key2order.iter() {
-            if order == desired_order {
-                return key;
-            }
-        }
-        panic!(
-            "Order {:?} does not be associated with any key",
-            desired_order
-        );
+    pub fn keys(&self) -> &KeyMap {
+        &self.keys
     }
 }
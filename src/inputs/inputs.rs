@@ -1,38 +1,60 @@
-use super::{EventReader, KeyConverter, Order};
+use super::{EventReader, InputEvent, KeyAction, KeyMap, KittyEvents, Order};
 use failure::{Fail, Fallible};
 use std::io;
 use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use std::thread;
-use termion::event::{Event, Key};
+use termion::event::Event;
 use termion::input::Events;
 
-pub type EventResult = io::Result<Event>;
+pub type EventResult = io::Result<InputEvent>;
 
 // https://users.rust-lang.org/t/alias-for-trait-bounds/8198
-pub trait EventStream: Iterator<Item = EventResult> + Send {}
-impl<R: io::Read + Send> EventStream for Events<R> {}
+pub trait EventStream: Iterator<Item = io::Result<Self::Event>> + Send {
+    type Event: Into<InputEvent>;
+
+    // Whether key releases are reported.
+    const REPORTS_RELEASE: bool;
+}
+
+impl<R: io::Read + Send> EventStream for Events<R> {
+    type Event = Event;
+    const REPORTS_RELEASE: bool = false;
+}
+
+impl<R: io::Read + Send> EventStream for KittyEvents<R> {
+    type Event = InputEvent;
+    const REPORTS_RELEASE: bool = true;
+}
 
 pub struct Inputs {
     receiver: Receiver<EventResult>,
     converter: EventReader,
+    reports_release: bool,
 }
 
 impl Inputs {
-    pub fn new<ES: 'static + EventStream>(events: ES, key: KeyConverter) -> Inputs {
+    pub fn new<ES: 'static + EventStream>(events: ES, keys: KeyMap) -> Inputs {
         let (sender, receiver) = channel();
         thread::spawn(move || {
             for event in events {
-                sender.send(event).expect("send event from Inputs");
+                sender
+                    .send(event.map(Into::into))
+                    .expect("send event from Inputs");
             }
         });
 
-        let converter = EventReader::new(key);
+        let converter = EventReader::new(keys);
         Inputs {
             receiver,
             converter,
+            reports_release: ES::REPORTS_RELEASE,
         }
     }
 
+    pub fn reports_release(&self) -> bool {
+        self.reports_release
+    }
+
     pub fn recv_event(&mut self) -> Fallible<EventResult> {
         let event = self
             .receiver
@@ -49,11 +71,15 @@ impl Inputs {
         }
     }
 
+    // Waits for an order given by a key press or repeat.
     pub fn recv_order(&mut self) -> Fallible<io::Result<Order>> {
         loop {
             match self.recv_event()? {
-                Ok(event) => {
-                    if let Some(order) = self.converter.order(event) {
+                Ok(input) => {
+                    if input.action == KeyAction::Release {
+                        continue;
+                    }
+                    if let Some(order) = self.converter.order(input.event) {
                         return Ok(Ok(order));
                     }
                 }
@@ -64,11 +90,14 @@ impl Inputs {
         }
     }
 
-    pub fn try_recv_order(&mut self) -> Fallible<Option<io::Result<Order>>> {
+    pub fn try_recv_order(&mut self) -> Fallible<Option<io::Result<(Order, KeyAction)>>> {
         match self.try_recv_event()? {
             Some(event) => {
                 let order = match event {
-                    Ok(event) => self.converter.order(event).map(Ok),
+                    Ok(input) => {
+                        let action = input.action;
+                        self.converter.order(input.event).map(|o| Ok((o, action)))
+                    }
                     Err(err) => Some(Err(err)),
                 };
                 Ok(order)
@@ -77,7 +106,67 @@ impl Inputs {
         }
     }
 
-    pub fn bound_key(&self, order: Order) -> Key {
-        self.converter.bound_key(order)
+    pub fn keys(&self) -> &KeyMap {
+        self.converter.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coord::Dir;
+    use crate::inputs::KeyConverter;
+    use termion::input::TermRead;
+
+    fn recv_orders(inputs: &mut Inputs, n: usize) -> Vec<Order> {
+        (0..n)
+            .map(|_| inputs.recv_order().unwrap().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn orders_from_kitty_events() {
+        let bytes = b"\x1b[104u\x1b[104;1:2u\x1b[104;1:3u\x1b[108u\x1b[113u";
+        let mut inputs = Inputs::new(KittyEvents::new(&bytes[..]), KeyMap::new(KeyConverter::Vim));
+        assert!(inputs.reports_release());
+        assert_eq!(
+            recv_orders(&mut inputs, 4),
+            vec![
+                Order::Move(Dir::Left),
+                Order::Move(Dir::Left),
+                Order::Move(Dir::Right),
+                Order::Quit
+            ]
+        );
+    }
+
+    #[test]
+    fn orders_from_legacy_events() {
+        let bytes = b"\x1b[D\x1b[Cq";
+        let mut inputs = Inputs::new((&bytes[..]).events(), KeyMap::new(KeyConverter::Normal));
+        assert!(!inputs.reports_release());
+        assert_eq!(
+            recv_orders(&mut inputs, 3),
+            vec![Order::Move(Dir::Left), Order::Move(Dir::Right), Order::Quit]
+        );
+    }
+
+    #[test]
+    fn focus_lost_order() {
+        let bytes = b"\x1b[I\x1b[Op";
+        let mut inputs = Inputs::new((&bytes[..]).events(), KeyMap::new(KeyConverter::Normal));
+        assert_eq!(
+            recv_orders(&mut inputs, 2),
+            vec![Order::FocusLost, Order::Pause]
+        );
+
+        let mut inputs = Inputs::new(
+            KittyEvents::new(&bytes[..]),
+            KeyMap::new(KeyConverter::Normal),
+        );
+        assert_eq!(
+            recv_orders(&mut inputs, 2),
+            vec![Order::FocusLost, Order::Pause]
+        );
     }
 }
@@ -1,46 +1,403 @@
 use super::Order;
 use crate::coord::{Dir, RotateDir};
 use std::collections::HashMap;
+use std::fmt;
 use termion::event::Key;
 
-macro_rules! hash_map {
-    ($($key:expr => $val:expr),*$(,)*) => {{
-        let mut m = HashMap::new();
-        $(m.insert($key, $val);)*
-        m
-    }}
-}
+// Orders which must have keys to play and to leave a game.
+const ESSENTIAL_ORDERS: [Order; 6] = [
+    Order::Move(Dir::Left),
+    Order::Move(Dir::Right),
+    Order::Rotate(RotateDir::Clockwise),
+    Order::HardDrop,
+    Order::Select,
+    Order::Quit,
+];
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyConverter {
     Normal,
     Vim,
 }
 
 impl KeyConverter {
-    pub fn key_to_order(self) -> HashMap<Key, Order> {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyConverter::Normal => "normal",
+            KeyConverter::Vim => "vim",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyConverter> {
+        [KeyConverter::Normal, KeyConverter::Vim]
+            .iter()
+            .copied()
+            .find(|k| k.name() == name)
+    }
+
+    pub fn bindings(self) -> Vec<(Key, Order)> {
         use super::Order::*;
         match self {
-            KeyConverter::Normal => hash_map! {
-                Key::Left => Move(Dir::Left),
-                Key::Right => Move(Dir::Right),
-                Key::Down => Move(Dir::Down),
-                Key::Char('d') => Rotate(RotateDir::AntiClockwise),
-                Key::Char('f') => Rotate(RotateDir::Clockwise),
-                Key::Char('\n') => Select,
-                Key::Char('?') => Help,
-                Key::Char('q') => Quit,
-            },
-            KeyConverter::Vim => hash_map! {
-                Key::Char('h') => Move(Dir::Left),
-                Key::Char('l') => Move(Dir::Right),
-                Key::Char('j') => Move(Dir::Down),
-                Key::Char('d') => Rotate(RotateDir::AntiClockwise),
-                Key::Char('f') => Rotate(RotateDir::Clockwise),
-                Key::Char('\n') => Select,
-                Key::Char('?') => Help,
-                Key::Char('q') => Quit,
-            },
+            KeyConverter::Normal => vec![
+                (Key::Left, Move(Dir::Left)),
+                (Key::Right, Move(Dir::Right)),
+                (Key::Down, Move(Dir::Down)),
+                (Key::Up, SonicDrop),
+                (Key::Char(' '), HardDrop),
+                (Key::Char('d'), Rotate(RotateDir::AntiClockwise)),
+                (Key::Char('f'), Rotate(RotateDir::Clockwise)),
+                (Key::Char('c'), Hold),
+                (Key::Char('\n'), Select),
+                (Key::Char('p'), Pause),
+                (Key::Char('?'), Help),
+                (Key::Char('q'), Quit),
+            ],
+            KeyConverter::Vim => vec![
+                (Key::Char('h'), Move(Dir::Left)),
+                (Key::Char('l'), Move(Dir::Right)),
+                (Key::Char('j'), Move(Dir::Down)),
+                (Key::Char('k'), SonicDrop),
+                (Key::Char(' '), HardDrop),
+                (Key::Char('d'), Rotate(RotateDir::AntiClockwise)),
+                (Key::Char('f'), Rotate(RotateDir::Clockwise)),
+                (Key::Char('c'), Hold),
+                (Key::Char('\n'), Select),
+                (Key::Char('p'), Pause),
+                (Key::Char('?'), Help),
+                (Key::Char('q'), Quit),
+            ],
+        }
+    }
+}
+
+// BindError tells which order was bound to make the key map invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindError {
+    pub order: Order,
+    pub message: String,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// KeyMap binds keys to orders, starting from a preset. An order can have
+// any number of keys, but a key gives one order at most.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    preset: KeyConverter,
+    // Orders whose keys are given by the user instead of the preset.
+    custom: Vec<(Order, Vec<Key>)>,
+    bindings: Vec<(Key, Order)>,
+    key2order: HashMap<Key, Order>,
+}
+
+impl KeyMap {
+    pub fn new(preset: KeyConverter) -> Self {
+        let mut keys = KeyMap {
+            preset,
+            custom: Vec::new(),
+            bindings: Vec::new(),
+            key2order: HashMap::new(),
+        };
+        keys.rebuild();
+        keys
+    }
+
+    pub fn preset(&self) -> KeyConverter {
+        self.preset
+    }
+
+    pub fn set_preset(&mut self, preset: KeyConverter) {
+        self.preset = preset;
+        self.rebuild();
+    }
+
+    // Replaces the keys of the order. Keys of the preset are taken over,
+    // but the same key cannot be given to another order by the user.
+    pub fn bind(&mut self, order: Order, keys: &[Key]) -> Result<(), String> {
+        // Pieces never move up, and focus-lost is sent by the terminal.
+        if order == Order::Move(Dir::Up) || order == Order::FocusLost {
+            return Err(format!("{} cannot be bound to keys", order.name()));
+        }
+        for key in keys.iter() {
+            let bound = self
+                .custom
+                .iter()
+                .find(|(o, ks)| *o != order && ks.contains(key));
+            if let Some((other, _)) = bound {
+                return Err(format!(
+                    "{} is already bound to {}",
+                    key_name(*key),
+                    other.name()
+                ));
+            }
+        }
+
+        let mut unique = Vec::new();
+        for &key in keys.iter() {
+            if !unique.contains(&key) {
+                unique.push(key);
+            }
+        }
+        let keys = unique;
+        match self.custom.iter_mut().find(|(o, _)| *o == order) {
+            Some(custom) => custom.1 = keys,
+            None => self.custom.push((order, keys)),
+        }
+        self.rebuild();
+        Ok(())
+    }
+
+    fn rebuild(&mut self) {
+        let custom = &self.custom;
+        let is_custom =
+            |key: &Key, order: &Order| custom.iter().any(|(o, ks)| o == order || ks.contains(key));
+        let mut bindings: Vec<_> = self
+            .preset
+            .bindings()
+            .into_iter()
+            .filter(|(k, o)| !is_custom(k, o))
+            .collect();
+        for (order, keys) in custom.iter() {
+            bindings.extend(keys.iter().map(|&k| (k, *order)));
+        }
+
+        self.key2order = bindings.iter().copied().collect();
+        self.bindings = bindings;
+    }
+
+    // Checks the orders needed to play have keys, and that taking over
+    // keys of the preset did not leave its orders without any.
+    pub fn check(&self) -> Result<(), BindError> {
+        if let Some(&order) = ESSENTIAL_ORDERS
+            .iter()
+            .find(|&&order| self.keys(order).is_empty())
+        {
+            return Err(BindError {
+                order: self.taker(order).unwrap_or(order),
+                message: format!("no key is bound to {}", order.name()),
+            });
+        }
+        for (key, order) in self.preset.bindings() {
+            let is_custom = self.custom.iter().any(|(o, _)| *o == order);
+            if is_custom || !self.keys(order).is_empty() {
+                continue;
+            }
+            if let Some(other) = self.order(key) {
+                return Err(BindError {
+                    order: other,
+                    message: format!(
+                        "{} is bound to {}, leaving no key for {}",
+                        key_name(key),
+                        other.name(),
+                        order.name()
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the order which took over a key of the preset from the order.
+    fn taker(&self, order: Order) -> Option<Order> {
+        if self.custom.iter().any(|(o, _)| *o == order) {
+            return None;
+        }
+        self.preset
+            .bindings()
+            .into_iter()
+            .filter(|(_, o)| *o == order)
+            .find_map(|(k, _)| self.order(k))
+    }
+
+    pub fn order(&self, key: Key) -> Option<Order> {
+        self.key2order.get(&key).copied()
+    }
+
+    // Returns the keys of the order, the ones of the preset first.
+    pub fn keys(&self, order: Order) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, o)| *o == order)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+}
+
+// Keys are named like `a`, `space`, `left`, `f1` or `ctrl-a` in config files.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "space".to_string(),
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::BackTab => "backtab".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Delete => "delete".to_string(),
+        Key::Insert => "insert".to_string(),
+        Key::Esc => "esc".to_string(),
+        _ => "?".to_string(),
+    }
+}
+
+pub fn parse_key(name: &str) -> Option<Key> {
+    let key = match name {
+        "space" => Key::Char(' '),
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backtab" => Key::BackTab,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        _ => {
+            if let Some(c) = single_char(name.strip_prefix("ctrl-")) {
+                Key::Ctrl(c)
+            } else if let Some(c) = single_char(name.strip_prefix("alt-")) {
+                Key::Alt(c)
+            } else if let Some(c) = single_char(Some(name)) {
+                Key::Char(c)
+            } else {
+                match name.strip_prefix('f').map(str::parse::<u8>) {
+                    Some(Ok(n)) if (1..=12).contains(&n) => Key::F(n),
+                    _ => return None,
+                }
+            }
+        }
+    };
+    Some(key)
+}
+
+fn single_char(s: Option<&str>) -> Option<char> {
+    let mut chars = s?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid() {
+        for &preset in [KeyConverter::Normal, KeyConverter::Vim].iter() {
+            let keys = KeyMap::new(preset);
+            assert_eq!(keys.check(), Ok(()), "{}", preset.name());
+            assert_eq!(
+                keys.bindings.len(),
+                keys.key2order.len(),
+                "{}",
+                preset.name()
+            );
+        }
+    }
+
+    #[test]
+    fn bind_keys() {
+        let mut keys = KeyMap::new(KeyConverter::Normal);
+        keys.bind(Order::Move(Dir::Left), &[Key::Left, Key::Char('a')])
+            .unwrap();
+        keys.bind(Order::Hold, &[Key::Char('d'), Key::Char('c')])
+            .unwrap();
+
+        assert_eq!(
+            keys.keys(Order::Move(Dir::Left)),
+            vec![Key::Left, Key::Char('a')]
+        );
+        assert_eq!(keys.order(Key::Char('a')), Some(Order::Move(Dir::Left)));
+
+        // The key is taken over from the preset, but its order needs another.
+        assert_eq!(keys.order(Key::Char('d')), Some(Order::Hold));
+        assert_eq!(keys.keys(Order::Rotate(RotateDir::AntiClockwise)), vec![]);
+        assert_eq!(
+            keys.check(),
+            Err(BindError {
+                order: Order::Hold,
+                message: "d is bound to hold, leaving no key for rotate-ccw".to_string(),
+            })
+        );
+        keys.bind(Order::Rotate(RotateDir::AntiClockwise), &[Key::Char('s')])
+            .unwrap();
+        assert_eq!(keys.check(), Ok(()));
+
+        keys.set_preset(KeyConverter::Vim);
+        assert_eq!(keys.order(Key::Char('a')), Some(Order::Move(Dir::Left)));
+        assert_eq!(keys.order(Key::Char('h')), None);
+        assert_eq!(keys.order(Key::Char('l')), Some(Order::Move(Dir::Right)));
+    }
+
+    #[test]
+    fn invalid_bindings() {
+        let mut keys = KeyMap::new(KeyConverter::Normal);
+        keys.bind(Order::Hold, &[Key::Char('a')]).unwrap();
+        assert_eq!(
+            keys.bind(Order::Pause, &[Key::Char('a')]),
+            Err("a is already bound to hold".to_string())
+        );
+        assert_eq!(
+            keys.bind(Order::FocusLost, &[Key::Char('b')]),
+            Err("focus-lost cannot be bound to keys".to_string())
+        );
+        assert_eq!(
+            keys.bind(Order::Move(Dir::Up), &[Key::Up]),
+            Err("move-up cannot be bound to keys".to_string())
+        );
+
+        keys.bind(Order::Hold, &[Key::Char('q')]).unwrap();
+        assert_eq!(
+            keys.check(),
+            Err(BindError {
+                order: Order::Hold,
+                message: "no key is bound to quit".to_string(),
+            })
+        );
+        keys.bind(Order::Quit, &[]).unwrap();
+        assert_eq!(keys.check().map_err(|e| e.order), Err(Order::Quit));
+    }
+
+    #[test]
+    fn key_names() {
+        let keys = [
+            Key::Char('a'),
+            Key::Char(' '),
+            Key::Char('\n'),
+            Key::Char('?'),
+            Key::Ctrl('x'),
+            Key::Alt('1'),
+            Key::F(5),
+            Key::Left,
+            Key::PageDown,
+            Key::Esc,
+        ];
+        for &key in keys.iter() {
+            assert_eq!(parse_key(&key_name(key)), Some(key), "{:?}", key);
         }
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("shift"), None);
     }
 }
@@ -0,0 +1,384 @@
+// Support of the kitty keyboard protocol, which reports key repeats and releases.
+// https://sw.kovidgoyal.net/kitty/keyboard-protocol/
+
+use super::{InputEvent, KeyAction};
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::event::{self, Event, Key};
+
+// Queries the keyboard flags and then the primary device attributes.
+// Every terminal answers the latter, so it marks the end of the answers.
+const QUERY: &[u8] = b"\x1b[?u\x1b[c";
+
+// Disambiguates escape codes (1), reports event types (2), reports
+// alternate keys (4) and reports all keys as escape codes (8).
+const ENABLE: &[u8] = b"\x1b[>15u";
+
+pub const DISABLE: &[u8] = b"\x1b[<u";
+
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+const ESC: u8 = 0x1b;
+
+// TtyInput reads bytes in a background thread so that the answers of
+// the terminal can be waited for with a timeout.
+pub struct TtyInput {
+    receiver: Receiver<io::Result<u8>>,
+}
+
+impl TtyInput {
+    pub fn new<R: 'static + Read + Send>(source: R) -> Self {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(source).bytes() {
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        TtyInput { receiver }
+    }
+
+    // Enables the protocol if the terminal answers the query for it.
+    // Keys typed before the answers arrive are discarded.
+    pub fn enable_kitty<W: Write>(&mut self, out: &mut W) -> io::Result<bool> {
+        out.write_all(QUERY)?;
+        out.flush()?;
+
+        let deadline = Instant::now() + QUERY_TIMEOUT;
+        let mut answers = Vec::new();
+        let supported = loop {
+            if let Some(supported) = parse_answers(&answers) {
+                break supported;
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(timeout) {
+                Ok(byte) => answers.push(byte?),
+                Err(RecvTimeoutError::Timeout) => break false,
+                Err(RecvTimeoutError::Disconnected) => return Ok(false),
+            }
+        };
+
+        if supported {
+            out.write_all(ENABLE)?;
+            out.flush()?;
+        }
+        Ok(supported)
+    }
+}
+
+impl Read for TtyInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = match self.receiver.recv() {
+            Ok(byte) => byte?,
+            Err(_) => return Ok(0),
+        };
+        let mut n = 1;
+        while n < buf.len() {
+            match self.receiver.try_recv() {
+                Ok(Ok(byte)) => buf[n] = byte,
+                _ => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+// Returns whether the keyboard flags were answered once the device
+// attributes are, or None while waiting for them.
+fn parse_answers(bytes: &[u8]) -> Option<bool> {
+    let mut flags = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"\x1b[?") {
+            let params = &bytes[i + 3..];
+            match params
+                .iter()
+                .position(|b| !b.is_ascii_digit() && *b != b';')
+            {
+                Some(end) => {
+                    match params[end] {
+                        b'u' => flags = true,
+                        b'c' => return Some(flags),
+                        _ => {}
+                    }
+                    i += 3 + end + 1;
+                }
+                None => return None,
+            }
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+// KittyEvents parses the bytes sent by a terminal with the protocol enabled.
+// Other sequences are parsed by termion as usual.
+pub struct KittyEvents<R> {
+    bytes: io::Bytes<BufReader<R>>,
+}
+
+impl<R: Read> KittyEvents<R> {
+    pub fn new(source: R) -> Self {
+        KittyEvents {
+            bytes: BufReader::new(source).bytes(),
+        }
+    }
+
+    fn next_csi(&mut self) -> io::Result<Vec<u8>> {
+        let mut seq = vec![ESC, b'['];
+        loop {
+            let byte = match self.bytes.next() {
+                Some(byte) => byte?,
+                None => return Ok(seq),
+            };
+            seq.push(byte);
+            // The final byte of a CSI sequence is in 0x40..=0x7E.
+            if (0x40..=0x7e).contains(&byte) {
+                return Ok(seq);
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for KittyEvents<R> {
+    type Item = io::Result<InputEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.bytes.next()? {
+            Ok(byte) => byte,
+            Err(err) => return Some(Err(err)),
+        };
+        if first != ESC {
+            let event = event::parse_event(first, &mut self.bytes);
+            let event = event.unwrap_or_else(|_| Event::Unsupported(vec![first]));
+            return Some(Ok(InputEvent::from(event)));
+        }
+
+        let second = match self.bytes.next() {
+            Some(Ok(byte)) => byte,
+            Some(Err(err)) => return Some(Err(err)),
+            None => return Some(Ok(InputEvent::from(Event::Key(Key::Esc)))),
+        };
+        if second != b'[' {
+            let mut rest = Some(Ok(second)).into_iter().chain(&mut self.bytes);
+            let event = event::parse_event(ESC, &mut rest);
+            let event = event.unwrap_or_else(|_| Event::Unsupported(vec![ESC, second]));
+            return Some(Ok(InputEvent::from(event)));
+        }
+
+        Some(self.next_csi().map(|seq| {
+            parse_csi_key(&seq).unwrap_or_else(|| InputEvent::from(Event::Unsupported(seq)))
+        }))
+    }
+}
+
+// Parses `CSI code[:alternates] ; modifiers[:action] u` and the legacy forms
+// of functional keys such as `CSI 1 ; modifiers[:action] A`.
+fn parse_csi_key(seq: &[u8]) -> Option<InputEvent> {
+    let (&last, params) = seq[2..].split_last()?;
+    let params = std::str::from_utf8(params).ok()?;
+    let mut fields = params.split(';');
+
+    let mut codes = fields.next().unwrap_or("").split(':');
+    let code = codes.next().filter(|c| !c.is_empty());
+    let shifted = codes.next().and_then(|c| c.parse::<u32>().ok());
+
+    let mut mods = fields.next().unwrap_or("").split(':');
+    let modifiers = match mods.next().filter(|m| !m.is_empty()) {
+        Some(m) => m.parse::<u32>().ok()?.saturating_sub(1),
+        None => 0,
+    };
+    let action = match mods.next() {
+        None | Some("1") => KeyAction::Press,
+        Some("2") => KeyAction::Repeat,
+        Some("3") => KeyAction::Release,
+        Some(_) => return None,
+    };
+
+    let key = match last {
+        b'u' => {
+            let code = code?.parse::<u32>().ok()?;
+            text_key(code, shifted, modifiers)?
+        }
+        b'~' => match code?.parse::<u32>().ok()? {
+            2 => Key::Insert,
+            3 => Key::Delete,
+            5 => Key::PageUp,
+            6 => Key::PageDown,
+            7 => Key::Home,
+            8 => Key::End,
+            n @ 11..=15 => Key::F((n - 10) as u8),
+            n @ 17..=21 => Key::F((n - 11) as u8),
+            n @ 23..=24 => Key::F((n - 12) as u8),
+            _ => return None,
+        },
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'P' => Key::F(1),
+        b'Q' => Key::F(2),
+        b'S' => Key::F(4),
+        b'Z' => Key::BackTab,
+        _ => return None,
+    };
+
+    Some(InputEvent {
+        event: Event::Key(key),
+        action,
+    })
+}
+
+const SHIFT: u32 = 1;
+const ALT: u32 = 2;
+const CTRL: u32 = 4;
+
+fn text_key(code: u32, shifted: Option<u32>, modifiers: u32) -> Option<Key> {
+    let key = match code {
+        9 => Key::Char('\t'),
+        13 => Key::Char('\n'),
+        27 => Key::Esc,
+        127 => Key::Backspace,
+        // Codes from 57344 are private use ones for keys like the keypad or modifiers.
+        57344.. => return None,
+        _ => {
+            let code = match shifted {
+                Some(shifted) if modifiers & SHIFT != 0 => shifted,
+                _ => code,
+            };
+            let chr = std::char::from_u32(code)?;
+            if modifiers & CTRL != 0 {
+                Key::Ctrl(chr)
+            } else if modifiers & ALT != 0 {
+                Key::Alt(chr)
+            } else {
+                Key::Char(chr)
+            }
+        }
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(bytes: &'static [u8]) -> Vec<InputEvent> {
+        KittyEvents::new(bytes).map(|e| e.unwrap()).collect()
+    }
+
+    fn key(key: Key, action: KeyAction) -> InputEvent {
+        InputEvent {
+            event: Event::Key(key),
+            action,
+        }
+    }
+
+    #[test]
+    fn parse_kitty_events() {
+        struct Case {
+            bytes: &'static [u8],
+            events: Vec<InputEvent>,
+        }
+        use self::KeyAction::*;
+        let cases = [
+            Case {
+                bytes: b"\x1b[104u\x1b[104;1:2u\x1b[104;1:3u",
+                events: vec![
+                    key(Key::Char('h'), Press),
+                    key(Key::Char('h'), Repeat),
+                    key(Key::Char('h'), Release),
+                ],
+            },
+            Case {
+                bytes: b"\x1b[1;1:1D\x1b[1;1:3D\x1b[C",
+                events: vec![
+                    key(Key::Left, Press),
+                    key(Key::Left, Release),
+                    key(Key::Right, Press),
+                ],
+            },
+            Case {
+                bytes: b"\x1b[47:63;2u\x1b[32u\x1b[13;1:3u\x1b[99;5u",
+                events: vec![
+                    key(Key::Char('?'), Press),
+                    key(Key::Char(' '), Press),
+                    key(Key::Char('\n'), Release),
+                    key(Key::Ctrl('c'), Press),
+                ],
+            },
+            Case {
+                bytes: b"\x1b[3;1:3~\x1b[57441;2u",
+                events: vec![
+                    key(Key::Delete, Release),
+                    InputEvent::from(Event::Unsupported(b"\x1b[57441;2u".to_vec())),
+                ],
+            },
+            Case {
+                bytes: b"q\x1b[A",
+                events: vec![key(Key::Char('q'), Press), key(Key::Up, Press)],
+            },
+        ];
+
+        for Case {
+            bytes,
+            events: expected,
+        } in cases.iter()
+        {
+            assert_eq!(events(bytes), *expected, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn parse_query_answers() {
+        struct Case {
+            bytes: &'static [u8],
+            supported: Option<bool>,
+        }
+        let cases = [
+            Case {
+                bytes: b"\x1b[?0u\x1b[?62;22c",
+                supported: Some(true),
+            },
+            Case {
+                bytes: b"\x1b[?62;22c",
+                supported: Some(false),
+            },
+            Case {
+                bytes: b"j\x1b[?1u\x1b[?6",
+                supported: None,
+            },
+            Case {
+                bytes: b"",
+                supported: None,
+            },
+        ];
+
+        for Case { bytes, supported } in cases.iter() {
+            assert_eq!(parse_answers(bytes), *supported, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn enable_when_answered() {
+        let mut out = Vec::new();
+        let mut input = TtyInput::new(&b"\x1b[?0u\x1b[?62c"[..]);
+        assert!(input.enable_kitty(&mut out).unwrap());
+        assert_eq!(out, [QUERY, ENABLE].concat());
+
+        let mut out = Vec::new();
+        let mut input = TtyInput::new(&b"\x1b[?62c"[..]);
+        assert!(!input.enable_kitty(&mut out).unwrap());
+        assert_eq!(out, QUERY);
+    }
+}
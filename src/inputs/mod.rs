@@ -1,19 +1,120 @@
 use super::coord::{Dir, RotateDir};
+use termion::event::Event;
 
 pub use self::event_reader::EventReader;
-pub use self::inputs::Inputs;
-pub use self::keys::KeyConverter;
+pub use self::inputs::{EventStream, Inputs};
+pub use self::keys::{key_name, parse_key, KeyConverter, KeyMap};
+pub use self::kitty::{KittyEvents, TtyInput};
 
 mod event_reader;
 #[allow(clippy::module_inception)]
 mod inputs;
 mod keys;
+pub mod kitty;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Order {
     Move(Dir),
     Rotate(RotateDir),
+    HardDrop,
+    SonicDrop,
+    Hold,
     Select,
+    Pause,
+    // Sent by the terminal instead of a key when it loses focus.
+    FocusLost,
     Help,
     Quit,
 }
+
+const ORDERS: [Order; 14] = [
+    Order::Move(Dir::Left),
+    Order::Move(Dir::Right),
+    Order::Move(Dir::Down),
+    Order::Move(Dir::Up),
+    Order::Rotate(RotateDir::Clockwise),
+    Order::Rotate(RotateDir::AntiClockwise),
+    Order::HardDrop,
+    Order::SonicDrop,
+    Order::Hold,
+    Order::Select,
+    Order::Pause,
+    Order::FocusLost,
+    Order::Help,
+    Order::Quit,
+];
+
+impl Order {
+    pub fn all() -> &'static [Order] {
+        &ORDERS
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Order::Move(Dir::Left) => "move-left",
+            Order::Move(Dir::Right) => "move-right",
+            Order::Move(Dir::Down) => "move-down",
+            Order::Move(Dir::Up) => "move-up",
+            Order::Rotate(RotateDir::Clockwise) => "rotate-cw",
+            Order::Rotate(RotateDir::AntiClockwise) => "rotate-ccw",
+            Order::HardDrop => "hard-drop",
+            Order::SonicDrop => "sonic-drop",
+            Order::Hold => "hold",
+            Order::Select => "select",
+            Order::Pause => "pause",
+            Order::FocusLost => "focus-lost",
+            Order::Help => "help",
+            Order::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Order> {
+        ORDERS.iter().copied().find(|o| o.name() == name)
+    }
+}
+
+// Terminals supporting focus events report focus changes as `CSI I` and `CSI O`.
+pub const ENABLE_FOCUS_EVENTS: &[u8] = b"\x1b[?1004h";
+pub const DISABLE_FOCUS_EVENTS: &[u8] = b"\x1b[?1004l";
+const FOCUS_OUT: &[u8] = b"\x1b[O";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Press,
+    Repeat,
+    Release,
+}
+
+impl KeyAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::Press => "press",
+            KeyAction::Repeat => "repeat",
+            KeyAction::Release => "release",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyAction> {
+        [KeyAction::Press, KeyAction::Repeat, KeyAction::Release]
+            .iter()
+            .copied()
+            .find(|a| a.name() == name)
+    }
+}
+
+// InputEvent is an event with the action of the key.
+// Terminals without the kitty keyboard protocol report only presses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputEvent {
+    pub event: Event,
+    pub action: KeyAction,
+}
+
+impl From<Event> for InputEvent {
+    fn from(event: Event) -> Self {
+        InputEvent {
+            event,
+            action: KeyAction::Press,
+        }
+    }
+}
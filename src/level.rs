@@ -0,0 +1,84 @@
+// Gravity is measured in 1/256 rows per frame, so G means one row every frame.
+pub const G: u32 = 256;
+const G20: u32 = 20 * G;
+
+pub const MAX_LEVEL: u32 = 20;
+pub const LINES_PER_LEVEL: usize = 10;
+
+// Rows per frame at 60 FPS for each level, following the guideline curve
+// (0.8 - (level - 1) * 0.007) ^ (level - 1) seconds per row.
+// The last level falls at 20G, which puts pieces on the ground instantly.
+const GRAVITY_TABLE: [u32; MAX_LEVEL as usize] = [
+    4, 5, 7, 9, 12, 16, 22, 32, 45, 67, 99, 151, 235, 373, 604, 1001, 1693, 2928, 5120, G20,
+];
+
+pub fn gravity(level: u32) -> u32 {
+    let idx = level.clamp(1, MAX_LEVEL) - 1;
+    GRAVITY_TABLE[idx as usize]
+}
+
+pub fn level(start_level: u32, lines: usize) -> u32 {
+    (start_level + (lines / LINES_PER_LEVEL) as u32).min(MAX_LEVEL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_by_lines() {
+        struct Case {
+            start: u32,
+            lines: usize,
+            level: u32,
+        }
+        let cases = [
+            Case {
+                start: 1,
+                lines: 0,
+                level: 1,
+            },
+            Case {
+                start: 1,
+                lines: 9,
+                level: 1,
+            },
+            Case {
+                start: 1,
+                lines: 10,
+                level: 2,
+            },
+            Case {
+                start: 5,
+                lines: 35,
+                level: 8,
+            },
+            Case {
+                start: 15,
+                lines: 999,
+                level: MAX_LEVEL,
+            },
+        ];
+
+        for (
+            i,
+            Case {
+                start,
+                lines,
+                level: lv,
+            },
+        ) in cases.iter().enumerate()
+        {
+            assert_eq!(level(*start, *lines), *lv, "test[{}]", i);
+        }
+    }
+
+    #[test]
+    fn gravity_gets_faster() {
+        for lv in 1..MAX_LEVEL {
+            assert!(gravity(lv) <= gravity(lv + 1), "level {}", lv);
+        }
+        assert_eq!(gravity(MAX_LEVEL), G20);
+        assert_eq!(gravity(MAX_LEVEL + 1), G20);
+    }
+}
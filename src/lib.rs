@@ -4,17 +4,30 @@
 extern crate failure;
 extern crate getopts;
 extern crate rand;
+extern crate rand_pcg;
 extern crate termion;
 
 pub mod action;
+mod auto_shift;
 mod block;
 pub mod cli;
 mod color;
+mod config;
+mod controller;
 pub mod coord;
 mod elapsed;
 mod field;
 pub mod game;
 pub mod inputs;
+mod level;
+mod paths;
 mod play;
+pub mod randomizer;
+pub mod replay;
+pub mod rules;
+pub mod scores;
+mod scoring;
 pub mod screen;
 mod tetromino;
+mod theme;
+mod time;
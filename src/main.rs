@@ -1,13 +1,20 @@
+extern crate failure;
 extern crate termion;
 extern crate textris;
 
-use std::{env, io, process};
+use failure::{Error, Fallible};
+use std::io::{self, StdoutLock, Write};
+use std::path::Path;
+use std::{env, process};
 use termion::input::TermRead;
-use termion::raw::IntoRawMode;
+use termion::raw::{IntoRawMode, RawTerminal};
 use textris::cli::{self, CliParsed, Config};
-use textris::game::Game;
-use textris::inputs::Inputs;
-use textris::screen::Screen;
+use textris::game::{self, Game};
+use textris::inputs::{self, kitty, Inputs, KittyEvents, TtyInput};
+use textris::replay::Replay;
+use textris::rules::Rules;
+use textris::scores::ScoreTable;
+use textris::screen::{self, Screen};
 
 enum Exit {
     Ok,
@@ -32,27 +39,125 @@ fn main() {
                 Exit::Ok
             }
             CliParsed::Run(config) => run(config),
+            CliParsed::Replay(config, path) => replay(config, &path),
+            CliParsed::Verify(path) => verify(&path),
+            CliParsed::Scores => scores(),
         },
         Err(err) => {
-            println!("ERR: {}", err);
-            for e in err.iter_causes() {
-                println!("  {}", e);
-            }
+            print_error(&err);
             Exit::Err(1)
         }
     };
     process::exit(exit_code.code());
 }
 
+fn print_error(err: &Error) {
+    println!("ERR: {}", err);
+    for e in err.iter_causes() {
+        println!("  {}", e);
+    }
+}
+
 fn run(conf: Config) -> Exit {
+    with_game(&conf, conf.rules(), |game| game.start())
+}
+
+fn replay(conf: Config, path: &Path) -> Exit {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            print_error(&err);
+            return Exit::Err(1);
+        }
+    };
+    with_game(&conf, replay.rules, |game| game.replay(&replay))
+}
+
+fn verify(path: &Path) -> Exit {
+    match Replay::load(path).and_then(|replay| replay.verify().map(|_| replay)) {
+        Ok(replay) => {
+            println!(
+                "OK: score {}, lines {}, frames {}",
+                replay.score, replay.lines, replay.frames
+            );
+            Exit::Ok
+        }
+        Err(err) => {
+            print_error(&err);
+            Exit::Err(1)
+        }
+    }
+}
+
+fn scores() -> Exit {
+    match ScoreTable::load() {
+        Ok(table) => {
+            let lines = table.listing();
+            if lines.is_empty() {
+                println!("No scores yet");
+            }
+            for line in lines {
+                println!("{}", line);
+            }
+            Exit::Ok
+        }
+        Err(err) => {
+            print_error(&err);
+            Exit::Err(1)
+        }
+    }
+}
+
+// TerminalModes turns off the modes enabled for the game when dropped,
+// even if the game panics.
+struct TerminalModes {
+    kitty: bool,
+}
+
+impl Drop for TerminalModes {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(inputs::DISABLE_FOCUS_EVENTS);
+        if self.kitty {
+            let _ = stdout.write_all(kitty::DISABLE);
+        }
+        let _ = stdout.flush();
+    }
+}
+
+// Sets up the terminal for the game and restores it after the game ends.
+fn with_game<F>(conf: &Config, rules: Rules, f: F) -> Exit
+where
+    F: FnOnce(&mut Game<RawTerminal<StdoutLock>>) -> Fallible<()>,
+{
+    let help_modal = game::make_help_modal(conf.keys());
+    if let Err(err) = screen::check_terminal_size(&rules, help_modal.content.len()) {
+        print_error(&err);
+        return Exit::Err(1);
+    }
+
     let stdout = io::stdout();
-    let stdout = stdout.lock().into_raw_mode().unwrap();
+    let mut stdout = stdout.lock().into_raw_mode().unwrap();
+
+    // Use the kitty keyboard protocol to know key releases if available.
+    let mut tty = TtyInput::new(io::stdin());
+    let kitty = tty.enable_kitty(&mut stdout).unwrap_or(false);
+    let _modes = TerminalModes { kitty };
+    let inputs = if kitty {
+        Inputs::new(KittyEvents::new(tty), conf.keys().clone())
+    } else {
+        Inputs::new(tty.events(), conf.keys().clone())
+    };
+
+    // Pause the game when the terminal loses focus.
+    let _ = stdout
+        .write_all(inputs::ENABLE_FOCUS_EVENTS)
+        .and_then(|_| stdout.flush());
 
-    let inputs = Inputs::new(io::stdin().events(), conf.key());
-    let screen = Screen::new(stdout);
-    let mut game = Game::new(inputs, screen);
+    let screen = Screen::new(stdout, conf.ghost(), conf.theme());
+    let mut game = Game::new(inputs, screen, rules, help_modal);
 
-    match game.start() {
+    match f(&mut game) {
         Ok(_) => Exit::Ok,
         Err(err) => {
             game.stop_by_error(err);
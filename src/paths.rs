@@ -0,0 +1,27 @@
+use failure::Fallible;
+use std::env;
+use std::path::PathBuf;
+
+// Data files are stored under $XDG_DATA_HOME/textris,
+// which is ~/.local/share/textris by default.
+pub fn data_dir() -> Fallible<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+// The config file is $XDG_CONFIG_HOME/textris/config,
+// which is ~/.config/textris/config by default.
+pub fn config_file() -> Fallible<PathBuf> {
+    Ok(xdg_dir("XDG_CONFIG_HOME", ".config")?.join("config"))
+}
+
+fn xdg_dir(var: &str, default: &str) -> Fallible<PathBuf> {
+    // Relative paths are invalid in the XDG spec and must be ignored.
+    let base = match env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(default),
+            None => return Err(format_err!("neither {} nor HOME is set", var)),
+        },
+    };
+    Ok(base.join("textris"))
+}
@@ -3,14 +3,40 @@ use crate::color::Color;
 use crate::coord::{Coord, Dir, Dirs, RotateDir};
 use crate::elapsed::Elapsed;
 use crate::field::Field;
-use crate::tetromino::{Tetromino, Tetrominos, N_TETROS};
-use rand::rngs::ThreadRng;
-use rand::{thread_rng, Rng};
-use std::collections::HashMap;
+use crate::level::{self, G};
+use crate::randomizer::Randomizer;
+use crate::rules::{Rules, SpawnPolicy};
+use crate::scoring::{LineClear, Scoring, Spin};
+use crate::tetromino::{TetroCoords, Tetromino, N_TETROS};
+use crate::time::{frames, FPS};
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use std::collections::{HashMap, VecDeque};
+
+// The number of hidden rows above the visible field.
+const VANISH_ZONE: usize = 20;
+
+// New pieces appear in the vanish zone right above the visible field.
+const SPAWN_Y: i8 = -2;
+
+// Milliseconds to wait before the next tetromino appears.
+const ENTRY_DELAY: u64 = 100;
+const LINE_CLEAR_DELAY: u64 = 400;
+
+// Milliseconds to show what the last lock achieved.
+const CLEAR_NOTICE: u64 = 2000;
+
+// Milliseconds to show the perfect clear banner.
+const PERFECT_CLEAR_NOTICE: u64 = 3000;
+
+// How many times moves and rotations can reset the lock delay
+// until the tetromino reaches a lower row.
+const MAX_LOCK_RESETS: usize = 15;
 
 struct Random<R: Rng> {
     rng: R,
-    tetros: Tetrominos,
+    randomizer: Box<dyn Randomizer>,
     dirs: Dirs,
 }
 
@@ -18,68 +44,100 @@ impl<R> Random<R>
 where
     R: Rng,
 {
-    pub fn new(rng: R) -> Self {
+    pub fn new(rng: R, randomizer: Box<dyn Randomizer>) -> Self {
         Random {
             rng,
-            tetros: Tetromino::all(),
+            randomizer,
             dirs: Dir::all(),
         }
     }
 
     pub fn random_tetro(&mut self) -> Tetromino {
-        *self.rng.choose(&self.tetros).unwrap()
+        self.randomizer.next(&mut self.rng)
     }
 
     pub fn random_tetro_dir(&mut self) -> Dir {
-        *self.rng.choose(&self.dirs).unwrap()
+        *self.dirs.choose(&mut self.rng).unwrap()
     }
 
-    pub fn random_tetro_pos(&mut self, width: usize) -> Coord {
-        Coord(self.rng.gen_range(0, width as i8), 0)
+    pub fn random_tetro_x(&mut self, width: usize) -> i8 {
+        self.rng.gen_range(0, width as i8)
     }
 }
 
 pub struct Play {
-    random: Random<ThreadRng>,
+    rules: Rules,
+    random: Random<Pcg32>,
     block_map: HashMap<Tetromino, Block>,
     tetro: Tetromino,
-    next_tetro: Tetromino,
+    next_tetros: VecDeque<Tetromino>,
+    held_tetro: Option<Tetromino>,
+    hold_used: bool,
     tetro_dir: Dir,
     tetro_stopped: bool,
     tetro_pos: Coord,
-    deletables: Option<Vec<usize>>,
+    lowest_y: i8,
+    fall_progress: u32,
+    lock_timer: u64,
+    lock_resets: usize,
+    last_kick: Option<usize>,
+    entry_timer: u64,
+    deletables: Vec<usize>,
     field: Field,
-    elapsed: Elapsed,
-    score: usize,
+    frame: u64,
+    scoring: Scoring,
+    lines: usize,
+    clear_notice: Option<LineClear>,
+    clear_notice_timer: u64,
+    perfect_clears: usize,
+    perfect_clear_timer: u64,
 }
 
-impl Default for Play {
-    fn default() -> Self {
-        let mut random = Random::new(thread_rng());
-        let next_tetro = random.random_tetro();
+impl Play {
+    // Plays with the same rules deal the same tetrominos. Rules without
+    // a seed get a random one, which is kept in the rules of the play.
+    // Unlike StdRng, Pcg32 keeps its algorithm across versions and platforms.
+    pub fn new(rules: Rules) -> Self {
+        let seed = rules.seed.unwrap_or_else(|| thread_rng().gen());
+        let rules = Rules {
+            seed: Some(seed),
+            ..rules
+        };
+        let rng = Pcg32::seed_from_u64(seed);
+        let mut random = Random::new(rng, rules.randomizer.build());
+        let next_tetros = (0..rules.preview).map(|_| random.random_tetro()).collect();
 
         let mut play = Play {
+            rules,
             random,
             block_map: Play::default_block_map(),
             tetro: Tetromino::I, // temp
-            next_tetro,
+            next_tetros,
+            held_tetro: None,
+            hold_used: false,
             tetro_dir: Default::default(),
             tetro_stopped: false,
             tetro_pos: Default::default(),
-            deletables: None,
-            field: Field::new(16, 16),
-            elapsed: Elapsed::new(),
-            score: 0,
+            lowest_y: SPAWN_Y,
+            fall_progress: 0,
+            lock_timer: 0,
+            lock_resets: 0,
+            last_kick: None,
+            entry_timer: 0,
+            deletables: Vec::new(),
+            field: Field::new(rules.width, rules.height, VANISH_ZONE),
+            frame: 0,
+            scoring: Scoring::new(),
+            lines: 0,
+            clear_notice: None,
+            clear_notice_timer: 0,
+            perfect_clears: 0,
+            perfect_clear_timer: 0,
         };
-        play.drop_tetro();
+        play.drop_tetro()
+            .expect("the first tetromino is always placeable");
         play
     }
-}
-
-impl Play {
-    pub fn new() -> Self {
-        Play::default()
-    }
 
     fn default_block_map() -> HashMap<Tetromino, Block> {
         let bm = HashMap::with_capacity(N_TETROS);
@@ -89,11 +147,46 @@ impl Play {
         })
     }
 
-    fn drop_tetro(&mut self) {
-        self.tetro = self.next_tetro;
-        self.next_tetro = self.random.random_tetro();
+    fn drop_tetro(&mut self) -> Result<(), ()> {
+        let tetro = self.next_tetros.pop_front().unwrap();
+        self.next_tetros.push_back(self.random.random_tetro());
+        self.spawn_tetro(tetro)
+    }
+
+    // Spawning fails when the tetromino overlaps existing blocks (block out).
+    fn spawn_tetro(&mut self, tetro: Tetromino) -> Result<(), ()> {
+        self.tetro = tetro;
+        match self.rules.spawn {
+            SpawnPolicy::Guideline => {
+                self.tetro_dir = Dir::Up;
+                self.tetro_pos = Coord((self.field.width() as i8 - 3) / 2, SPAWN_Y);
+                if !self.field.is_movable(&self.tetro_coords()) {
+                    return Err(());
+                }
+            }
+            SpawnPolicy::Chaos => self.spawn_randomly()?,
+        }
+
+        // The tetromino drops one row immediately if nothing blocks it.
+        let below = self.tetro_pos + Dir::Down.to_coord();
+        if self
+            .field
+            .is_movable(&self.tetro.make_coords(below, self.tetro_dir))
+        {
+            self.tetro_pos = below;
+        }
+
+        self.lowest_y = self.tetro_pos.y();
+        self.last_kick = None;
+        self.fall_progress = 0;
+        self.lock_timer = 0;
+        self.lock_resets = 0;
+        Ok(())
+    }
+
+    fn spawn_randomly(&mut self) -> Result<(), ()> {
         self.tetro_dir = self.random.random_tetro_dir();
-        self.tetro_pos = self.random.random_tetro_pos(self.field.width());
+        self.tetro_pos = Coord(self.random.random_tetro_x(self.field.width()), SPAWN_Y);
 
         let dir = if self.tetro_pos.x() < (self.field.width() as i8) / 2 {
             Dir::Right
@@ -102,98 +195,281 @@ impl Play {
         };
         let adjustment = dir.to_coord();
 
-        // Find renderable position.
-        loop {
-            let coords = self.tetro.make_coords(self.tetro_pos, self.tetro_dir);
-            if self.field.is_movable(&coords) {
-                let block = self.block();
-                self.field.render_blocks(block, &coords);
-                break;
-            } else {
-                self.tetro_pos += adjustment;
+        // Find renderable position, until the tetromino box of 4x4 cells
+        // leaves the field.
+        let width = self.field.width() as i8;
+        while (-3..width).contains(&self.tetro_pos.x()) {
+            if self.field.is_movable(&self.tetro_coords()) {
+                return Ok(());
             }
+            self.tetro_pos += adjustment;
         }
+        Err(())
+    }
+
+    fn tetro_coords(&self) -> TetroCoords {
+        self.tetro.make_coords(self.tetro_pos, self.tetro_dir)
+    }
+
+    fn landing_pos(&self) -> Coord {
+        let down = Dir::Down.to_coord();
+        let mut pos = self.tetro_pos;
+        while self
+            .field
+            .is_movable(&self.tetro.make_coords(pos + down, self.tetro_dir))
+        {
+            pos += down;
+        }
+        pos
     }
 
     fn block(&self) -> Block {
         *self.block_map.get(&self.tetro).unwrap()
     }
 
-    pub fn next_tetro_hint(&self) -> Block {
-        *self.block_map.get(&self.next_tetro).unwrap()
+    pub fn next_tetro_hints(&self) -> Vec<(Block, TetroCoords)> {
+        self.next_tetros
+            .iter()
+            .map(|&t| (*self.block_map.get(&t).unwrap(), t.preview_coords()))
+            .collect()
+    }
+
+    pub fn held_tetro_hint(&self) -> Option<Block> {
+        self.held_tetro.map(|t| *self.block_map.get(&t).unwrap())
+    }
+
+    pub fn falling_tetro(&self) -> Option<(Block, TetroCoords)> {
+        if self.tetro_stopped {
+            None
+        } else {
+            Some((self.block(), self.tetro_coords()))
+        }
+    }
+
+    // Ghost coords show where the falling tetromino would land if dropped straight down.
+    pub fn ghost_coords(&self) -> Option<TetroCoords> {
+        if self.tetro_stopped {
+            None
+        } else {
+            Some(self.tetro.make_coords(self.landing_pos(), self.tetro_dir))
+        }
     }
 
     pub fn field(&self) -> &Field {
         &self.field
     }
 
-    pub fn elapsed(&self) -> &Elapsed {
-        &self.elapsed
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.rules.seed.expect("seed is set by Play::new")
+    }
+
+    // The number of frames played so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn elapsed(&self) -> Elapsed {
+        Elapsed::from_millis(self.frame * 1000 / FPS)
     }
 
     pub fn score(&self) -> usize {
-        self.score
+        self.scoring.score()
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn level(&self) -> u32 {
+        level::level(self.rules.start_level, self.lines)
     }
 
+    pub fn combo(&self) -> usize {
+        self.scoring.combo()
+    }
+
+    pub fn back_to_back(&self) -> bool {
+        self.scoring.back_to_back()
+    }
+
+    pub fn clear_notice(&self) -> Option<LineClear> {
+        self.clear_notice
+    }
+
+    pub fn perfect_clears(&self) -> usize {
+        self.perfect_clears
+    }
+
+    pub fn is_perfect_clear_shown(&self) -> bool {
+        self.perfect_clear_timer > 0
+    }
+
+    // Update advances the play by one frame.
     pub fn update(&mut self) -> Result<(), ()> {
-        if let Some(deletables) = self.deletables.take() {
-            for &i in deletables.iter() {
-                self.field.delete_line(i);
+        self.frame += 1;
+        if self.clear_notice_timer > 0 {
+            self.clear_notice_timer -= 1;
+            if self.clear_notice_timer == 0 {
+                self.clear_notice = None;
             }
-            self.score += deletables.len();
-            self.deletables = None;
-            self.drop_tetro();
         }
+        self.perfect_clear_timer = self.perfect_clear_timer.saturating_sub(1);
 
         if self.tetro_stopped {
+            if self.entry_timer > 0 {
+                self.entry_timer -= 1;
+                return Ok(());
+            }
+            for &i in self.deletables.iter() {
+                self.field.delete_line(i);
+            }
+            self.deletables.clear();
             self.tetro_stopped = false;
-            return Ok(());
+            return self.drop_tetro();
         }
 
-        match self.move_tetro(Dir::Down) {
-            Ok(_) => {}
-            Err(_) => {
-                if self.field.is_reached() {
-                    return Err(());
-                }
+        self.fall_progress += level::gravity(self.level());
+        while self.fall_progress >= G {
+            self.fall_progress -= G;
+            if self.move_tetro(Dir::Down).is_err() {
+                self.fall_progress = 0;
+                break;
+            }
+        }
 
-                self.tetro_stopped = true;
-                match self.mark_deletable_lines() {
-                    None => {
-                        self.drop_tetro();
-                    }
-                    some => {
-                        self.deletables = some;
-                    }
-                };
+        if self.is_landed() {
+            self.lock_timer += 1;
+            if self.lock_timer >= frames(self.rules.lock_delay) {
+                return self.lock_tetro();
             }
-        };
+        }
         Ok(())
     }
 
-    pub fn tick(&mut self) {
-        self.elapsed.add_secs(1);
+    fn is_landed(&self) -> bool {
+        let below = self.tetro_pos + Dir::Down.to_coord();
+        !self
+            .field
+            .is_movable(&self.tetro.make_coords(below, self.tetro_dir))
+    }
+
+    // Moving or rotating the tetromino on the ground gives some more time before locking.
+    fn reset_lock_delay(&mut self) {
+        if self.tetro_pos.y() > self.lowest_y {
+            self.lowest_y = self.tetro_pos.y();
+            self.lock_timer = 0;
+            self.lock_resets = 0;
+        } else if self.lock_timer > 0 && self.lock_resets < MAX_LOCK_RESETS {
+            self.lock_timer = 0;
+            self.lock_resets += 1;
+        }
+    }
+
+    fn lock_tetro(&mut self) -> Result<(), ()> {
+        let block = self.block();
+        let coords = self.tetro_coords();
+        self.field.render_blocks(block, &coords);
+
+        // Lock out: the whole tetromino is locked in the vanish zone.
+        if coords.iter().all(|c| c.y() < 0) {
+            return Err(());
+        }
+
+        let spin = self.detect_spin();
+
+        self.tetro_stopped = true;
+        self.hold_used = false;
+        self.deletables = self.mark_deletable_lines();
+        let clear = LineClear::new(self.deletables.len(), spin);
+        self.scoring.line_clear(clear, self.level());
+        self.lines += clear.lines;
+        if clear.is_notable() {
+            self.clear_notice = Some(clear);
+            self.clear_notice_timer = frames(CLEAR_NOTICE);
+        }
+        if self.is_perfect_clear() {
+            self.scoring.perfect_clear(clear.lines, self.level());
+            self.perfect_clears += 1;
+            self.perfect_clear_timer = frames(PERFECT_CLEAR_NOTICE);
+        }
+        self.entry_timer = if self.deletables.is_empty() {
+            frames(ENTRY_DELAY)
+        } else {
+            frames(LINE_CLEAR_DELAY)
+        };
+        Ok(())
     }
 
     pub fn slide_tetro(&mut self, dir: Dir) {
-        if dir != Dir::Up {
-            let _ = self.move_tetro(dir);
+        if dir == Dir::Up || self.tetro_stopped {
+            return;
+        }
+        if self.move_tetro(dir).is_ok() && dir == Dir::Down {
+            self.scoring.soft_drop(1);
         }
     }
 
+    // The tetromino can be held only once until it is locked.
+    pub fn hold_tetro(&mut self) -> Result<(), ()> {
+        if self.hold_used || self.tetro_stopped {
+            return Ok(());
+        }
+
+        self.hold_used = true;
+        match self.held_tetro.replace(self.tetro) {
+            Some(held) => self.spawn_tetro(held),
+            None => self.drop_tetro(),
+        }
+    }
+
+    // Sonic drop moves the tetromino to the bottom but does not lock it.
+    pub fn sonic_drop(&mut self) {
+        let distance = self.drop_to_bottom();
+        self.scoring.soft_drop(distance);
+    }
+
+    pub fn hard_drop(&mut self) -> Result<(), ()> {
+        if self.tetro_stopped {
+            return Ok(());
+        }
+        let distance = self.drop_to_bottom();
+        self.scoring.hard_drop(distance);
+        self.lock_tetro()
+    }
+
+    fn drop_to_bottom(&mut self) -> usize {
+        if self.tetro_stopped {
+            return 0;
+        }
+        let landing_pos = self.landing_pos();
+        let distance = landing_pos.y() - self.tetro_pos.y();
+        self.tetro_pos = landing_pos;
+        if distance > 0 {
+            self.last_kick = None;
+            self.reset_lock_delay();
+        }
+        distance as usize
+    }
+
     pub fn rotate_tetro(&mut self, rotate_dir: RotateDir) {
-        let current_coords = self.tetro.make_coords(self.tetro_pos, self.tetro_dir);
-        self.field.clear_blocks(&current_coords);
+        if self.tetro_stopped {
+            return;
+        }
 
         let dir = rotate_dir.rotate(self.tetro_dir);
-        let coords = self.tetro.make_coords(self.tetro_pos, dir);
-        let block = self.block();
-
-        if self.field.is_movable(&coords) {
-            self.tetro_dir = dir;
-            self.field.render_blocks(block, &coords);
-        } else {
-            self.field.render_blocks(block, &current_coords);
+        for (i, &kick) in self.tetro.kicks(self.tetro_dir, dir).iter().enumerate() {
+            let pos = self.tetro_pos + kick;
+            if self.field.is_movable(&self.tetro.make_coords(pos, dir)) {
+                self.tetro_pos = pos;
+                self.tetro_dir = dir;
+                self.last_kick = Some(i);
+                self.reset_lock_delay();
+                return;
+            }
         }
     }
 
@@ -202,24 +478,59 @@ impl Play {
             return Ok(());
         }
 
-        let current_coords = self.tetro.make_coords(self.tetro_pos, self.tetro_dir);
-        self.field.clear_blocks(&current_coords);
-
         let new_pos = self.tetro_pos + dir.to_coord();
         let coords = self.tetro.make_coords(new_pos, self.tetro_dir);
-        let block = self.block();
-
         if self.field.is_movable(&coords) {
-            self.field.render_blocks(block, &coords);
             self.tetro_pos = new_pos;
+            self.last_kick = None;
+            self.reset_lock_delay();
             Ok(())
         } else {
-            self.field.render_blocks(block, &current_coords);
             Err(())
         }
     }
 
-    fn mark_deletable_lines(&mut self) -> Option<Vec<usize>> {
+    // Detects a T-spin by the 3-corner rule: a T locked right after a rotation
+    // with 3 of the 4 corners around its centre occupied. It is a mini T-spin
+    // unless both corners it points to are occupied or the rotation used the last kick.
+    fn detect_spin(&self) -> Spin {
+        if self.tetro != Tetromino::T || self.last_kick.is_none() {
+            return Spin::None;
+        }
+
+        let is_occupied = |x: i8, y: i8| {
+            let pos = self.tetro_pos + Coord(x, y);
+            !self.field.is_in_range(pos) || self.field[pos].is_some()
+        };
+        let (front, back) = match self.tetro_dir {
+            Dir::Up => ([(0, 0), (2, 0)], [(0, 2), (2, 2)]),
+            Dir::Right => ([(2, 0), (2, 2)], [(0, 0), (0, 2)]),
+            Dir::Down => ([(0, 2), (2, 2)], [(0, 0), (2, 0)]),
+            Dir::Left => ([(0, 0), (0, 2)], [(2, 0), (2, 2)]),
+        };
+        let front = front.iter().filter(|&&(x, y)| is_occupied(x, y)).count();
+        let back = back.iter().filter(|&&(x, y)| is_occupied(x, y)).count();
+
+        if front + back < 3 {
+            Spin::None
+        } else if front == 2 || self.last_kick == Some(4) {
+            Spin::Full
+        } else {
+            Spin::Mini
+        }
+    }
+
+    // A perfect clear leaves no blocks after deleting the marked lines.
+    fn is_perfect_clear(&self) -> bool {
+        !self.deletables.is_empty()
+            && self
+                .field
+                .lines_iter()
+                .enumerate()
+                .all(|(i, line)| self.deletables.contains(&i) || line.iter().all(|c| c.is_none()))
+    }
+
+    fn mark_deletable_lines(&mut self) -> Vec<usize> {
         let targets: Vec<usize> = self
             .field
             .lines_iter()
@@ -235,6 +546,473 @@ impl Play {
             self.field.set_line(y, marked_line);
         }
 
-        Some(targets)
+        targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::randomizer::RandomizerKind;
+    use crate::rules::{MAX_FIELD_SIZE, MAX_PREVIEW};
+    use std::iter;
+
+    fn seeded(seed: u64) -> Rules {
+        Rules {
+            seed: Some(seed),
+            ..Rules::default()
+        }
+    }
+
+    fn spawn_next(play: &mut Play) {
+        while play.tetro_stopped {
+            play.update().unwrap();
+        }
+    }
+
+    fn land_o(play: &mut Play) {
+        play.spawn_tetro(Tetromino::O).unwrap();
+        play.sonic_drop();
+    }
+
+    #[test]
+    fn hold_takes_next_tetro_when_empty() {
+        let mut play = Play::new(seeded(0));
+        let (tetro, next) = (play.tetro, play.next_tetros[0]);
+
+        play.hold_tetro().unwrap();
+        assert_eq!(play.held_tetro, Some(tetro));
+        assert_eq!(play.tetro, next);
+    }
+
+    // Seeds are kept in replays and scores, so the tetrominos dealt
+    // by a seed must never change.
+    #[test]
+    fn fixed_seed_deals_fixed_tetrominos() {
+        let rules = Rules {
+            randomizer: RandomizerKind::Random,
+            preview: MAX_PREVIEW,
+            ..seeded(42)
+        };
+        let mut play = Play::new(rules);
+        let mut dealt: String = iter::once(play.tetro)
+            .chain(play.next_tetros.iter().cloned())
+            .map(|t| t.default_char())
+            .collect();
+        for _ in 0..7 {
+            play.hard_drop().unwrap();
+            spawn_next(&mut play);
+            dealt.push(play.next_tetros.back().unwrap().default_char());
+        }
+        assert_eq!(dealt, "TSLJOZIILOJJJO");
+    }
+
+    #[test]
+    fn next_tetros_move_forward() {
+        let mut play = Play::new(seeded(0));
+        let next: Vec<_> = play.next_tetros.iter().cloned().collect();
+        assert_eq!(next.len(), Rules::default().preview);
+
+        play.hard_drop().unwrap();
+        spawn_next(&mut play);
+        assert_eq!(play.tetro, next[0]);
+        assert_eq!(play.next_tetros.len(), next.len());
+        assert!(play.next_tetros.iter().zip(&next[1..]).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn hold_only_once_per_drop() {
+        let mut play = Play::new(seeded(0));
+        play.hold_tetro().unwrap();
+        let (tetro, held) = (play.tetro, play.held_tetro);
+
+        play.hold_tetro().unwrap();
+        assert_eq!(play.tetro, tetro);
+        assert_eq!(play.held_tetro, held);
+    }
+
+    #[test]
+    fn hold_again_after_lock() {
+        let mut play = Play::new(seeded(0));
+        play.hold_tetro().unwrap();
+        let held = play.held_tetro.unwrap();
+
+        play.hard_drop().unwrap();
+        spawn_next(&mut play);
+        let tetro = play.tetro;
+
+        play.hold_tetro().unwrap();
+        assert_eq!(play.tetro, held);
+        assert_eq!(play.held_tetro, Some(tetro));
+    }
+
+    #[test]
+    fn elapsed_counts_frames() {
+        let mut play = Play::new(seeded(0));
+        for _ in 0..FPS * 3 / 2 {
+            play.update().unwrap();
+        }
+        assert_eq!(play.frame(), 90);
+        assert_eq!(play.elapsed().millis(), 1500);
+    }
+
+    #[test]
+    fn ghost_lands_on_floor() {
+        let play = Play::new(seeded(0));
+        let bottom = play.field.height() as i8 - 1;
+
+        let ghost = play.ghost_coords().unwrap();
+        assert_eq!(ghost.iter().map(|c| c.y()).max(), Some(bottom));
+    }
+
+    #[test]
+    fn rotate_kicks_off_wall() {
+        let mut play = Play::new(seeded(0));
+        play.tetro = Tetromino::T;
+        play.tetro_dir = Dir::Right;
+        play.tetro_pos = Coord(-1, 5);
+
+        play.rotate_tetro(RotateDir::Clockwise);
+        assert_eq!(play.tetro_dir, Dir::Down);
+        assert_eq!(play.tetro_pos, Coord(0, 5));
+    }
+
+    #[test]
+    fn guideline_spawn_is_centred() {
+        use Tetromino::*;
+        struct Case {
+            width: usize,
+            tetros: &'static [Tetromino],
+            columns: (i8, i8),
+        }
+        let cases = [
+            Case {
+                width: 10,
+                tetros: &[T, J, L, S, Z],
+                columns: (3, 5),
+            },
+            Case {
+                width: 10,
+                tetros: &[O],
+                columns: (4, 5),
+            },
+            Case {
+                width: 10,
+                tetros: &[I],
+                columns: (3, 6),
+            },
+            Case {
+                width: 16,
+                tetros: &[T],
+                columns: (6, 8),
+            },
+        ];
+
+        for c in cases.iter() {
+            let rules = Rules {
+                width: c.width,
+                ..seeded(0)
+            };
+            let mut play = Play::new(rules);
+            for &t in c.tetros.iter() {
+                play.spawn_tetro(t).unwrap();
+                let xs: Vec<_> = play.tetro_coords().iter().map(|p| p.x()).collect();
+                let columns = (*xs.iter().min().unwrap(), *xs.iter().max().unwrap());
+                assert_eq!(play.tetro_dir, Dir::Up, "{:?}", t);
+                assert_eq!(columns, c.columns, "{} {:?}", c.width, t);
+            }
+        }
+    }
+
+    #[test]
+    fn block_out_when_spawn_is_occupied() {
+        let mut play = Play::new(seeded(0));
+        let block = play.block();
+        let coords: Vec<Coord> = (0..play.field.width() as i8)
+            .map(|x| Coord(x, SPAWN_Y + 1))
+            .collect();
+        play.field.render_blocks(block, &coords);
+
+        assert_eq!(play.drop_tetro(), Err(()));
+    }
+
+    #[test]
+    fn chaos_block_out_on_wide_field() {
+        for seed in 0..10 {
+            let rules = Rules {
+                width: MAX_FIELD_SIZE,
+                spawn: SpawnPolicy::Chaos,
+                ..seeded(seed)
+            };
+            let mut play = Play::new(rules);
+            let block = play.block();
+            let coords: Vec<Coord> = (0..MAX_FIELD_SIZE as i8)
+                .flat_map(|x| (SPAWN_Y..SPAWN_Y + 4).map(move |y| Coord(x, y)))
+                .collect();
+            play.field.render_blocks(block, &coords);
+
+            assert_eq!(play.spawn_tetro(Tetromino::I), Err(()), "{}", seed);
+        }
+    }
+
+    #[test]
+    fn lock_out_when_locked_in_vanish_zone() {
+        let mut play = Play::new(seeded(0));
+        let block = play.block();
+        let width = play.field.width() as i8;
+        let coords: Vec<Coord> = (0..width - 1).map(|x| Coord(x, 0)).collect();
+        play.field.render_blocks(block, &coords);
+
+        play.tetro = Tetromino::O;
+        play.tetro_dir = Dir::Up;
+        play.tetro_pos = Coord(0, SPAWN_Y);
+        assert_eq!(play.hard_drop(), Err(()));
+    }
+
+    #[test]
+    fn no_lock_out_when_partly_visible() {
+        let mut play = Play::new(seeded(0));
+        let block = play.block();
+        let width = play.field.width() as i8;
+        let coords: Vec<Coord> = (0..width - 1).map(|x| Coord(x, 1)).collect();
+        play.field.render_blocks(block, &coords);
+
+        play.tetro = Tetromino::O;
+        play.tetro_dir = Dir::Up;
+        play.tetro_pos = Coord(0, SPAWN_Y);
+        assert_eq!(play.hard_drop(), Ok(()));
+    }
+
+    // Sonic drops wait for the lock delay, see lock_after_lock_delay.
+    #[test]
+    fn drop_to_landing_pos() {
+        struct Case {
+            title: &'static str,
+            hard: bool,
+            locked: bool,
+            score_per_cell: usize,
+        }
+        let cases = [
+            Case {
+                title: "hard drop",
+                hard: true,
+                locked: true,
+                score_per_cell: 2,
+            },
+            Case {
+                title: "sonic drop",
+                hard: false,
+                locked: false,
+                score_per_cell: 1,
+            },
+        ];
+
+        for c in cases.iter() {
+            let mut play = Play::new(seeded(0));
+            let bottom = play.rules.height as i8 - 1;
+            play.tetro = Tetromino::O;
+            play.tetro_dir = Dir::Up;
+            play.tetro_pos = Coord(0, SPAWN_Y);
+            let x = play.tetro_coords().iter().map(|p| p.x()).min().unwrap();
+            let block = play.block();
+            play.field.render_blocks(block, &[Coord(x, bottom)]);
+
+            if c.hard {
+                play.hard_drop().unwrap();
+            } else {
+                play.sonic_drop();
+            }
+            let lowest = play.tetro_coords().iter().map(|p| p.y()).max().unwrap();
+            assert_eq!(lowest, bottom - 1, "{}", c.title);
+            assert_eq!(play.tetro_stopped, c.locked, "{}", c.title);
+            let cells = (play.tetro_pos.y() - SPAWN_Y) as usize;
+            assert_eq!(play.score(), cells * c.score_per_cell, "{}", c.title);
+        }
+    }
+
+    #[test]
+    fn lock_after_lock_delay() {
+        let mut play = Play::new(seeded(0));
+        let delay = frames(play.rules.lock_delay);
+        land_o(&mut play);
+
+        for _ in 1..delay {
+            play.update().unwrap();
+        }
+        assert!(!play.tetro_stopped);
+        play.update().unwrap();
+        assert!(play.tetro_stopped);
+    }
+
+    #[test]
+    fn move_resets_lock_delay() {
+        let mut play = Play::new(seeded(0));
+        let delay = frames(play.rules.lock_delay);
+        land_o(&mut play);
+
+        for _ in 1..delay {
+            play.update().unwrap();
+        }
+        play.slide_tetro(Dir::Left);
+        for _ in 1..delay {
+            play.update().unwrap();
+        }
+        assert!(!play.tetro_stopped);
+        play.update().unwrap();
+        assert!(play.tetro_stopped);
+    }
+
+    #[test]
+    fn lock_delay_resets_are_limited() {
+        let mut play = Play::new(seeded(0));
+        let delay = frames(play.rules.lock_delay);
+        let dirs = [Dir::Left, Dir::Right];
+        land_o(&mut play);
+
+        for i in 0..MAX_LOCK_RESETS {
+            play.update().unwrap();
+            play.slide_tetro(dirs[i % 2]);
+        }
+        assert_eq!(play.lock_resets, MAX_LOCK_RESETS);
+
+        for i in 1..delay {
+            play.update().unwrap();
+            play.slide_tetro(dirs[i as usize % 2]);
+        }
+        assert!(!play.tetro_stopped);
+        play.update().unwrap();
+        assert!(play.tetro_stopped);
+    }
+
+    #[test]
+    fn reaching_lower_row_restores_resets() {
+        let mut play = Play::new(seeded(0));
+        play.tetro = Tetromino::O;
+        play.tetro_dir = Dir::Up;
+        play.tetro_pos = Coord(6, 0);
+        play.lowest_y = 0;
+        play.lock_resets = MAX_LOCK_RESETS;
+
+        play.slide_tetro(Dir::Down);
+        assert_eq!(play.lock_resets, 0);
+    }
+
+    #[test]
+    fn t_spin_double() {
+        let mut play = Play::new(seeded(0));
+        let block = play.block();
+        let width = play.field.width() as i8;
+        let bottom = play.field.height() as i8 - 1;
+
+        // A slot under an overhang at x = 4 which fits a T pointing down.
+        let mut coords: Vec<Coord> = (0..width)
+            .filter(|&x| x != 5)
+            .map(|x| Coord(x, bottom))
+            .collect();
+        coords.extend(
+            (0..width)
+                .filter(|x| !(4..=6).contains(x))
+                .map(|x| Coord(x, bottom - 1)),
+        );
+        coords.push(Coord(4, bottom - 2));
+        play.field.render_blocks(block, &coords);
+
+        play.tetro = Tetromino::T;
+        play.tetro_dir = Dir::Right;
+        play.tetro_pos = Coord(4, bottom - 2);
+        play.rotate_tetro(RotateDir::Clockwise);
+        assert_eq!(play.tetro_dir, Dir::Down);
+
+        play.hard_drop().unwrap();
+        let clear = LineClear::new(2, Spin::Full);
+        assert_eq!(play.clear_notice(), Some(clear));
+        assert_eq!(play.score(), 1200);
+    }
+
+    #[test]
+    fn detect_spin() {
+        struct Case<'a> {
+            title: &'a str,
+            blocks: &'a [Coord],
+            dir: Dir,
+            last_kick: Option<usize>,
+            spin: Spin,
+        }
+        let bottom = 15;
+        let cases = [
+            Case {
+                title: "no rotation",
+                blocks: &[Coord(0, bottom - 1)],
+                dir: Dir::Up,
+                last_kick: None,
+                spin: Spin::None,
+            },
+            Case {
+                title: "two corners",
+                blocks: &[],
+                dir: Dir::Up,
+                last_kick: Some(0),
+                spin: Spin::None,
+            },
+            Case {
+                title: "one front corner",
+                blocks: &[Coord(0, bottom - 1)],
+                dir: Dir::Up,
+                last_kick: Some(0),
+                spin: Spin::Mini,
+            },
+            Case {
+                title: "one front corner with the last kick",
+                blocks: &[Coord(0, bottom - 1)],
+                dir: Dir::Up,
+                last_kick: Some(4),
+                spin: Spin::Full,
+            },
+            Case {
+                title: "both front corners",
+                blocks: &[Coord(0, bottom - 1), Coord(2, bottom - 1)],
+                dir: Dir::Up,
+                last_kick: Some(0),
+                spin: Spin::Full,
+            },
+        ];
+
+        for Case {
+            title,
+            blocks,
+            dir,
+            last_kick,
+            spin,
+        } in cases.iter()
+        {
+            let mut play = Play::new(seeded(0));
+            assert_eq!(play.field.height() as i8 - 1, bottom);
+            let block = play.block();
+            play.field.render_blocks(block, blocks);
+            play.tetro = Tetromino::T;
+            play.tetro_dir = *dir;
+            play.tetro_pos = Coord(0, bottom - 1);
+            play.last_kick = *last_kick;
+            assert_eq!(play.detect_spin(), *spin, "{}", title);
+        }
+    }
+
+    #[test]
+    fn perfect_clear() {
+        let mut play = Play::new(seeded(0));
+        let block = play.block();
+        let width = play.field.width() as i8;
+        let bottom = play.field.height() as i8 - 1;
+        let coords: Vec<Coord> = (4..width).map(|x| Coord(x, bottom)).collect();
+        play.field.render_blocks(block, &coords);
+
+        play.tetro = Tetromino::I;
+        play.tetro_dir = Dir::Up;
+        play.tetro_pos = Coord(0, bottom - 1);
+        play.hard_drop().unwrap();
+
+        assert_eq!(play.perfect_clears(), 1);
+        assert!(play.is_perfect_clear_shown());
+        assert_eq!(play.score(), 100 + 800);
     }
 }
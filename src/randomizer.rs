@@ -0,0 +1,236 @@
+use crate::tetromino::Tetromino;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use std::collections::VecDeque;
+
+pub trait Randomizer {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Tetromino;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RandomizerKind {
+    #[default]
+    Random,
+    Bag7,
+    Bag14,
+    History,
+}
+
+impl RandomizerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RandomizerKind::Random => "random",
+            RandomizerKind::Bag7 => "bag7",
+            RandomizerKind::Bag14 => "bag14",
+            RandomizerKind::History => "history",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RandomizerKind> {
+        use self::RandomizerKind::*;
+        [Random, Bag7, Bag14, History]
+            .iter()
+            .copied()
+            .find(|k| k.name() == name)
+    }
+
+    pub fn build(self) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::Random => Box::new(PureRandom),
+            RandomizerKind::Bag7 => Box::new(Bag::new(1)),
+            RandomizerKind::Bag14 => Box::new(Bag::new(2)),
+            RandomizerKind::History => Box::new(History::new()),
+        }
+    }
+}
+
+// PureRandom picks each tetromino uniformly, so long droughts can happen.
+pub struct PureRandom;
+
+impl Randomizer for PureRandom {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Tetromino {
+        *Tetromino::all().choose(rng).unwrap()
+    }
+}
+
+// Bag deals shuffled sets containing every tetromino `copies` times.
+pub struct Bag {
+    copies: usize,
+    pieces: Vec<Tetromino>,
+}
+
+impl Bag {
+    pub fn new(copies: usize) -> Self {
+        Bag {
+            copies,
+            pieces: Vec::new(),
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Tetromino {
+        if self.pieces.is_empty() {
+            for _ in 0..self.copies {
+                self.pieces.extend_from_slice(&Tetromino::all());
+            }
+            self.pieces.shuffle(rng);
+        }
+        self.pieces.pop().unwrap()
+    }
+}
+
+const HISTORY_ROLLS: usize = 6;
+
+// History rerolls pieces found in the last 4 dealt ones, as TGM does.
+pub struct History {
+    history: VecDeque<Tetromino>,
+    first: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        use crate::tetromino::Tetromino::*;
+        History {
+            history: vec![Z, S, S, Z].into_iter().collect(),
+            first: true,
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl Randomizer for History {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Tetromino {
+        use crate::tetromino::Tetromino::*;
+
+        let piece = if self.first {
+            // The first piece is never an S, Z or O to avoid a forced overhang.
+            self.first = false;
+            *[I, J, L, T].choose(rng).unwrap()
+        } else {
+            let all = Tetromino::all();
+            let mut piece = *all.choose(rng).unwrap();
+            for _ in 1..HISTORY_ROLLS {
+                if !self.history.contains(&piece) {
+                    break;
+                }
+                piece = *all.choose(rng).unwrap();
+            }
+            piece
+        };
+
+        self.history.pop_front();
+        self.history.push_back(piece);
+        piece
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetromino::N_TETROS;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    fn deal(kind: RandomizerKind, n: usize) -> Vec<Tetromino> {
+        let mut rng = Pcg32::seed_from_u64(42);
+        let mut randomizer = kind.build();
+        (0..n).map(|_| randomizer.next(&mut rng)).collect()
+    }
+
+    fn count(pieces: &[Tetromino], t: Tetromino) -> usize {
+        pieces.iter().filter(|&&p| p == t).count()
+    }
+
+    fn max_gap(pieces: &[Tetromino], t: Tetromino) -> usize {
+        let mut last = None;
+        let mut gap = 0;
+        for (i, &p) in pieces.iter().enumerate() {
+            if p == t {
+                if let Some(l) = last {
+                    gap = gap.max(i - l - 1);
+                }
+                last = Some(i);
+            }
+        }
+        gap
+    }
+
+    #[test]
+    fn random_deals_every_piece() {
+        let pieces = deal(RandomizerKind::Random, N_TETROS * 100);
+        for &t in Tetromino::all().iter() {
+            assert!(count(&pieces, t) > 0, "{:?}", t);
+        }
+    }
+
+    #[test]
+    fn bags_contain_each_piece_equally() {
+        // The widest gap is between the first copies at the start of a bag
+        // and the last ones at the end of the next bag.
+        struct Case {
+            kind: RandomizerKind,
+            copies: usize,
+            max_gap: usize,
+        }
+        let cases = [
+            Case {
+                kind: RandomizerKind::Bag7,
+                copies: 1,
+                max_gap: 12,
+            },
+            Case {
+                kind: RandomizerKind::Bag14,
+                copies: 2,
+                max_gap: 24,
+            },
+        ];
+
+        for Case {
+            kind,
+            copies,
+            max_gap: expected_gap,
+        } in cases.iter()
+        {
+            let bag_size = N_TETROS * copies;
+            let pieces = deal(*kind, bag_size * 100);
+            for bag in pieces.chunks(bag_size) {
+                for &t in Tetromino::all().iter() {
+                    assert_eq!(count(bag, t), *copies, "{:?}: {:?}", kind, t);
+                }
+            }
+            for &t in Tetromino::all().iter() {
+                assert!(max_gap(&pieces, t) <= *expected_gap, "{:?}: {:?}", kind, t);
+            }
+        }
+    }
+
+    #[test]
+    fn history_first_piece_is_not_s_z_o() {
+        for seed in 0..100 {
+            let mut rng = Pcg32::seed_from_u64(seed);
+            let first = History::new().next(&mut rng);
+            assert!(
+                ![Tetromino::S, Tetromino::Z, Tetromino::O].contains(&first),
+                "seed {}: {:?}",
+                seed,
+                first
+            );
+        }
+    }
+
+    #[test]
+    fn history_rarely_repeats() {
+        let n = N_TETROS * 1000;
+        let pieces = deal(RandomizerKind::History, n);
+        let repeats = pieces.windows(2).filter(|w| w[0] == w[1]).count();
+
+        // Pure random repeats the previous piece once in 7 draws.
+        assert!(repeats < n / 20, "{} repeats", repeats);
+    }
+}
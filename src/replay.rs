@@ -0,0 +1,434 @@
+use crate::controller::Controller;
+use crate::inputs::{KeyAction, Order};
+use crate::paths;
+use crate::play::Play;
+use crate::randomizer::RandomizerKind;
+use crate::rules::{Rules, SpawnPolicy};
+use crate::time::FPS;
+use failure::{Fail, Fallible};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC: &str = "textris-replay";
+
+// Bump this when the format or the simulation changes
+// so that old replays are not misread.
+pub const REPLAY_VERSION: u32 = 2;
+
+// Frames a play can last after the last order. Left alone at level 1,
+// pieces top out in about an hour on the tallest fields, so replays lasting
+// far longer are rejected rather than simulated for an unbounded time.
+const MAX_IDLE_FRAMES: u64 = FPS * 60 * 60 * 4;
+
+// Record is an order given to the play before the frame is updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub frame: u64,
+    pub order: Order,
+    pub action: KeyAction,
+}
+
+// Replay holds everything needed to play a game again, and its result.
+//
+// The file is a text file like below:
+//
+//   textris-replay 2
+//   seed 42
+//   width 16
+//   ...
+//   score 1200
+//   orders
+//   12 move-left press
+//   40 hard-drop press
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub rules: Rules,
+    pub release_events: bool,
+    pub records: Vec<Record>,
+    pub score: usize,
+    pub lines: usize,
+    pub frames: u64,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Fallible<Replay> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| e.context(format!("failed to read {}", path.display())))?;
+        let replay = text
+            .parse()
+            .map_err(|e: failure::Error| e.context(format!("invalid replay {}", path.display())))?;
+        Ok(replay)
+    }
+
+    pub fn playback(&self) -> Playback<'_> {
+        Playback {
+            replay: self,
+            ctrl: Controller::new(self.rules, self.release_events),
+            next: 0,
+            over: false,
+        }
+    }
+
+    // Simulates the game again without a terminal and checks that it ends
+    // with the recorded result.
+    pub fn verify(&self) -> Fallible<()> {
+        let mut playback = self.playback();
+        while !playback.is_finished() {
+            playback.step();
+        }
+
+        let play = playback.play();
+        let results = [
+            ("score", self.score as u64, play.score() as u64),
+            ("lines", self.lines as u64, play.lines() as u64),
+            ("frames", self.frames, play.frame()),
+        ];
+        let mismatches: Vec<_> = results
+            .iter()
+            .filter(|(_, recorded, simulated)| recorded != simulated)
+            .map(|(name, recorded, simulated)| {
+                format!("{} {} (recorded {})", name, simulated, recorded)
+            })
+            .collect();
+        if !mismatches.is_empty() {
+            return Err(format_err!("replay mismatch: {}", mismatches.join(", ")));
+        }
+        Ok(())
+    }
+
+    // Saves the replay into the data directory and returns its path.
+    pub fn save(&self) -> Fallible<PathBuf> {
+        let dir = paths::data_dir()?.join("replays");
+        fs::create_dir_all(&dir)
+            .map_err(|e| e.context(format!("failed to create {}", dir.display())))?;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let path = dir.join(format!("{}.replay", millis));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| e.context(format!("failed to create {}", path.display())))?;
+        write!(file, "{}", self)
+            .map_err(|e| e.context(format!("failed to write {}", path.display())))?;
+        Ok(path)
+    }
+}
+
+// Playback plays a replay again by giving the recorded orders at their frames.
+pub struct Playback<'a> {
+    replay: &'a Replay,
+    ctrl: Controller,
+    next: usize,
+    over: bool,
+}
+
+impl<'a> Playback<'a> {
+    pub fn play(&self) -> &Play {
+        self.ctrl.play()
+    }
+
+    // The playback finishes when the game is over. It also stops after the
+    // recorded frames in case the replay does not reproduce the game.
+    pub fn is_finished(&self) -> bool {
+        self.over || self.ctrl.play().frame() > self.replay.frames
+    }
+
+    // Advances the play by one frame.
+    pub fn step(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+        let frame = self.ctrl.play().frame();
+        while let Some(rec) = self.replay.records.get(self.next) {
+            if rec.frame != frame {
+                break;
+            }
+            self.next += 1;
+            if self.ctrl.apply(rec.order, rec.action).is_err() {
+                self.over = true;
+                return;
+            }
+        }
+        if self.ctrl.update().is_err() {
+            self.over = true;
+        }
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = &self.rules;
+        writeln!(f, "{} {}", MAGIC, REPLAY_VERSION)?;
+        if let Some(seed) = r.seed {
+            writeln!(f, "seed {}", seed)?;
+        }
+        writeln!(f, "randomizer {}", r.randomizer.name())?;
+        writeln!(f, "spawn {}", r.spawn.name())?;
+        writeln!(f, "width {}", r.width)?;
+        writeln!(f, "height {}", r.height)?;
+        writeln!(f, "lock-delay {}", r.lock_delay)?;
+        writeln!(f, "level {}", r.start_level)?;
+        writeln!(f, "das {}", r.das)?;
+        writeln!(f, "arr {}", r.arr)?;
+        writeln!(f, "preview {}", r.preview)?;
+        writeln!(f, "release-events {}", self.release_events)?;
+        writeln!(f, "score {}", self.score)?;
+        writeln!(f, "lines {}", self.lines)?;
+        writeln!(f, "frames {}", self.frames)?;
+        writeln!(f, "orders")?;
+        for rec in self.records.iter() {
+            writeln!(
+                f,
+                "{} {} {}",
+                rec.frame,
+                rec.order.name(),
+                rec.action.name()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Replay {
+    type Err = failure::Error;
+
+    fn from_str(text: &str) -> Fallible<Replay> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim()));
+
+        match lines.next() {
+            Some((_, header)) if header.starts_with(MAGIC) => {
+                let version = header[MAGIC.len()..].trim();
+                if version != REPLAY_VERSION.to_string() {
+                    return Err(format_err!("unsupported replay version: {}", version));
+                }
+            }
+            _ => return Err(format_err!("line 1: not a replay file")),
+        }
+
+        let mut rules = Rules::default();
+        let mut seed = None;
+        let mut release_events = false;
+        let (mut score, mut lines_cleared, mut frames) = (0, 0, 0);
+        let mut in_orders = false;
+        let mut records = Vec::new();
+
+        for (n, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format_err!("line {}: {}", n, msg);
+
+            if in_orders {
+                let fields: Vec<_> = line.split_whitespace().collect();
+                let record = match fields[..] {
+                    [frame, order, action] => Record {
+                        frame: parse_value(frame).map_err(err)?,
+                        order: Order::from_name(order)
+                            .ok_or_else(|| err(format!("unknown order: {}", order)))?,
+                        action: KeyAction::from_name(action)
+                            .ok_or_else(|| err(format!("unknown key action: {}", action)))?,
+                    },
+                    _ => return Err(err(format!("invalid order: {}", line))),
+                };
+                if records
+                    .last()
+                    .is_some_and(|r: &Record| r.frame > record.frame)
+                {
+                    return Err(err("orders are not in frame order".to_string()));
+                }
+                records.push(record);
+                continue;
+            }
+
+            if line == "orders" {
+                in_orders = true;
+                continue;
+            }
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                None => return Err(err(format!("missing value: {}", line))),
+            };
+            match key {
+                "seed" => seed = Some(parse_value(value).map_err(err)?),
+                "randomizer" => {
+                    rules.randomizer = RandomizerKind::from_name(value)
+                        .ok_or_else(|| err(format!("invalid randomizer: {}", value)))?
+                }
+                "spawn" => {
+                    rules.spawn = SpawnPolicy::from_name(value)
+                        .ok_or_else(|| err(format!("invalid spawn policy: {}", value)))?
+                }
+                "width" => rules.width = parse_value(value).map_err(err)?,
+                "height" => rules.height = parse_value(value).map_err(err)?,
+                "lock-delay" => rules.lock_delay = parse_value(value).map_err(err)?,
+                "level" => rules.start_level = parse_value(value).map_err(err)?,
+                "das" => rules.das = parse_value(value).map_err(err)?,
+                "arr" => rules.arr = parse_value(value).map_err(err)?,
+                "preview" => rules.preview = parse_value(value).map_err(err)?,
+                "release-events" => release_events = parse_value(value).map_err(err)?,
+                "score" => score = parse_value(value).map_err(err)?,
+                "lines" => lines_cleared = parse_value(value).map_err(err)?,
+                "frames" => frames = parse_value(value).map_err(err)?,
+                _ => return Err(err(format!("unknown key: {}", key))),
+            }
+        }
+
+        rules.seed = Some(seed.ok_or_else(|| format_err!("seed is missing"))?);
+        rules.check()?;
+        let last_frame = records.last().map_or(0, |r| r.frame);
+        if frames > last_frame.saturating_add(MAX_IDLE_FRAMES) {
+            return Err(format_err!(
+                "replay lasts too long after the last order: {} frames",
+                frames
+            ));
+        }
+        Ok(Replay {
+            rules,
+            release_events,
+            records,
+            score,
+            lines: lines_cleared,
+            frames,
+        })
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coord::Dir;
+
+    fn replay() -> Replay {
+        Replay {
+            rules: Rules {
+                width: 10,
+                height: 20,
+                seed: Some(42),
+                ..Rules::default()
+            },
+            release_events: true,
+            records: vec![
+                Record {
+                    frame: 3,
+                    order: Order::Move(Dir::Left),
+                    action: KeyAction::Press,
+                },
+                Record {
+                    frame: 20,
+                    order: Order::Move(Dir::Left),
+                    action: KeyAction::Release,
+                },
+                Record {
+                    frame: 20,
+                    order: Order::HardDrop,
+                    action: KeyAction::Press,
+                },
+            ],
+            score: 36,
+            lines: 0,
+            frames: 120,
+        }
+    }
+
+    #[test]
+    fn replay_round_trip() {
+        let replay = replay();
+        let text = replay.to_string();
+        assert!(text.starts_with("textris-replay 2\n"));
+        assert_eq!(text.parse::<Replay>().unwrap(), replay);
+    }
+
+    #[test]
+    fn verify_replays() {
+        let rules = Rules {
+            seed: Some(7),
+            ..Rules::default()
+        };
+        let mut ctrl = Controller::new(rules, false);
+        while ctrl.apply(Order::HardDrop, KeyAction::Press).is_ok() && ctrl.update().is_ok() {}
+        let replay = ctrl.to_replay();
+        assert!(replay.score > 0);
+        assert!(replay.verify().is_ok());
+
+        let tampered = Replay {
+            score: replay.score + 100,
+            ..replay.clone()
+        };
+        let err = tampered.verify().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "replay mismatch: score {} (recorded {})",
+                replay.score, tampered.score
+            )
+        );
+
+        // The last order may be a hard drop which tops out just as gravity
+        // would, so the first one is dropped to change the result.
+        let mut cut = replay.clone();
+        cut.records.remove(0);
+        assert!(cut.verify().is_err());
+    }
+
+    #[test]
+    fn invalid_replays() {
+        struct Case<'a> {
+            from: &'a str,
+            to: &'a str,
+            error: &'a str,
+        }
+        let cases = [
+            Case {
+                from: "textris-replay 2",
+                to: "textris-replay 1",
+                error: "unsupported replay version: 1",
+            },
+            Case {
+                from: "width 10",
+                to: "width ten",
+                error: "line 5: invalid value: ten",
+            },
+            Case {
+                from: "width 10",
+                to: "width 1",
+                error: "width must be between 4 and 100 (got 1)",
+            },
+            Case {
+                from: "20 hard-drop press",
+                to: "20 hard-drop push",
+                error: "line 19: unknown key action: push",
+            },
+            Case {
+                from: "3 move-left press",
+                to: "30 move-left press",
+                error: "line 18: orders are not in frame order",
+            },
+            Case {
+                from: "frames 120",
+                to: "frames 18446744073709551615",
+                error: "replay lasts too long after the last order: 18446744073709551615 frames",
+            },
+        ];
+
+        for Case { from, to, error } in cases.iter() {
+            let text = replay().to_string().replace(from, to);
+            match text.parse::<Replay>() {
+                Ok(_) => panic!("{} should be invalid", to),
+                Err(err) => assert_eq!(err.to_string(), *error),
+            }
+        }
+    }
+}
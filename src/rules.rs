@@ -0,0 +1,111 @@
+use crate::level::MAX_LEVEL;
+use crate::randomizer::RandomizerKind;
+use failure::Fallible;
+
+pub const MIN_FIELD_WIDTH: usize = 4;
+pub const MIN_FIELD_HEIGHT: usize = 4;
+
+// Coordinates are stored as i8, so keep some room for pieces sticking out of the field.
+pub const MAX_FIELD_SIZE: usize = 100;
+
+pub const MAX_PREVIEW: usize = 6;
+
+// Rules holds the settings which change how a game is played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rules {
+    pub randomizer: RandomizerKind,
+    pub spawn: SpawnPolicy,
+    pub width: usize,
+    pub height: usize,
+    // Milliseconds a landed tetromino waits before locking.
+    pub lock_delay: u64,
+    pub start_level: u32,
+    // Milliseconds a direction key is held before the tetromino starts to auto shift.
+    pub das: u64,
+    // Milliseconds between auto shifts. Zero shifts to the wall at once.
+    pub arr: u64,
+    // The number of upcoming tetrominos shown.
+    pub preview: usize,
+    // Every game uses this seed if given, or a random one.
+    pub seed: Option<u64>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            randomizer: RandomizerKind::default(),
+            spawn: SpawnPolicy::default(),
+            width: 16,
+            height: 16,
+            lock_delay: 500,
+            start_level: 1,
+            das: 167,
+            arr: 33,
+            preview: 3,
+            seed: None,
+        }
+    }
+}
+
+impl Rules {
+    // Games are ranked among ones of the same mode, which is named after
+    // the rules that change the difficulty.
+    pub fn mode(&self) -> String {
+        format!(
+            "{} {} {}x{} level {} lock {}",
+            self.randomizer.name(),
+            self.spawn.name(),
+            self.width,
+            self.height,
+            self.start_level,
+            self.lock_delay
+        )
+    }
+
+    // Checks the values which can be given from outside are playable.
+    pub fn check(&self) -> Fallible<()> {
+        let ranges = [
+            ("width", self.width, MIN_FIELD_WIDTH, MAX_FIELD_SIZE),
+            ("height", self.height, MIN_FIELD_HEIGHT, MAX_FIELD_SIZE),
+            ("level", self.start_level as usize, 1, MAX_LEVEL as usize),
+            ("preview", self.preview, 1, MAX_PREVIEW),
+        ];
+        for &(name, value, min, max) in ranges.iter() {
+            if value < min || max < value {
+                return Err(format_err!(
+                    "{} must be between {} and {} (got {})",
+                    name,
+                    min,
+                    max,
+                    value
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpawnPolicy {
+    // Pieces appear centred at the top in their spawn orientation.
+    #[default]
+    Guideline,
+    // Pieces appear at a random column in a random orientation.
+    Chaos,
+}
+
+impl SpawnPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            SpawnPolicy::Guideline => "guideline",
+            SpawnPolicy::Chaos => "chaos",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SpawnPolicy> {
+        [SpawnPolicy::Guideline, SpawnPolicy::Chaos]
+            .iter()
+            .copied()
+            .find(|p| p.name() == name)
+    }
+}
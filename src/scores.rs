@@ -0,0 +1,346 @@
+use crate::elapsed::Elapsed;
+use crate::paths;
+use failure::{Fail, Fallible};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC: &str = "textris-scores";
+const VERSION: u32 = 1;
+
+// The number of scores kept for each mode.
+pub const MAX_SCORES: usize = 10;
+
+pub const MAX_NAME_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub mode: String,
+    pub name: String,
+    pub score: usize,
+    pub lines: usize,
+    pub time: Elapsed,
+    // Seconds since the Unix epoch.
+    pub date: u64,
+    pub seed: u64,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Names are shown in a table, so only characters with a fixed width are allowed.
+pub fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_' || c == '.'
+}
+
+// The login name is used until the player enters another one.
+pub fn default_name() -> String {
+    let name: String = env::var("USER")
+        .unwrap_or_default()
+        .chars()
+        .filter(|&c| is_name_char(c))
+        .take(MAX_NAME_LEN)
+        .collect();
+    if name.is_empty() {
+        "player".to_string()
+    } else {
+        name
+    }
+}
+
+// ScoreTable keeps the high scores of each mode, from the highest.
+//
+// The file is a text file with a score per line, whose fields are
+// separated by tabs: mode, score, lines, time, date, seed and name.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScoreTable {
+    scores: Vec<Score>,
+}
+
+impl ScoreTable {
+    pub fn load() -> Fallible<ScoreTable> {
+        let path = paths::data_dir()?.join("scores");
+        match fs::read_to_string(&path) {
+            Ok(text) => text
+                .parse()
+                .map_err(|e: failure::Error| e.context(format!("invalid {}", path.display())))
+                .map_err(Into::into),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(ScoreTable::default()),
+            Err(e) => Err(e
+                .context(format!("failed to read {}", path.display()))
+                .into()),
+        }
+    }
+
+    pub fn save(&self) -> Fallible<()> {
+        let dir = paths::data_dir()?;
+        fs::create_dir_all(&dir)
+            .map_err(|e| e.context(format!("failed to create {}", dir.display())))?;
+
+        // Write to another file first so that a failure does not lose the scores.
+        let path = dir.join("scores");
+        let tmp = dir.join("scores.tmp");
+        fs::write(&tmp, self.to_string())
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| e.context(format!("failed to write {}", path.display())))?;
+        Ok(())
+    }
+
+    pub fn modes(&self) -> Vec<&str> {
+        let mut modes: Vec<_> = self.scores.iter().map(|s| s.mode.as_str()).collect();
+        modes.sort_unstable();
+        modes.dedup();
+        modes
+    }
+
+    pub fn scores<'a>(&'a self, mode: &'a str) -> impl Iterator<Item = &'a Score> {
+        self.scores.iter().filter(move |s| s.mode == mode)
+    }
+
+    // Returns the rank from 0 the score would get, if it makes the table.
+    pub fn rank(&self, mode: &str, score: usize) -> Option<usize> {
+        if score == 0 {
+            return None;
+        }
+        // Earlier scores stay above the same new one.
+        let rank = self.scores(mode).filter(|s| s.score >= score).count();
+        if rank < MAX_SCORES {
+            Some(rank)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, score: Score) -> Option<usize> {
+        let rank = self.rank(&score.mode, score.score)?;
+        let mode = score.mode.clone();
+        let pos = self
+            .scores
+            .iter()
+            .position(|s| s.mode == mode && s.score < score.score)
+            .unwrap_or(self.scores.len());
+        self.scores.insert(pos, score);
+
+        if let Some(last) = self
+            .scores
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.mode == mode)
+            .nth(MAX_SCORES)
+            .map(|(i, _)| i)
+        {
+            self.scores.remove(last);
+        }
+        Some(rank)
+    }
+
+    // Formats the scores as a table for each mode.
+    pub fn listing(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for mode in self.modes() {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(mode.to_string());
+            lines.push(format!(
+                "{:>3}  {:<w$}  {:>8}  {:>5}  {:<12}  {:<10}  {}",
+                "#",
+                "Name",
+                "Score",
+                "Lines",
+                "Time",
+                "Date",
+                "Seed",
+                w = MAX_NAME_LEN
+            ));
+            for (i, s) in self.scores(mode).enumerate() {
+                lines.push(format!(
+                    "{:>3}  {:<w$}  {:>8}  {:>5}  {:<12}  {:<10}  {}",
+                    i + 1,
+                    s.name,
+                    s.score,
+                    s.lines,
+                    s.time,
+                    format_date(s.date),
+                    s.seed,
+                    w = MAX_NAME_LEN
+                ));
+            }
+        }
+        lines
+    }
+}
+
+impl fmt::Display for ScoreTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, VERSION)?;
+        for s in self.scores.iter() {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                s.mode,
+                s.score,
+                s.lines,
+                s.time.millis(),
+                s.date,
+                s.seed,
+                s.name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ScoreTable {
+    type Err = failure::Error;
+
+    fn from_str(text: &str) -> Fallible<ScoreTable> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l));
+        match lines.next() {
+            Some((_, header)) if header.trim() == format!("{} {}", MAGIC, VERSION) => {}
+            _ => return Err(format_err!("line 1: not a score file")),
+        }
+
+        let mut scores = Vec::new();
+        for (n, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let err = |msg: String| format_err!("line {}: {}", n, msg);
+            let fields: Vec<_> = line.split('\t').collect();
+            let score = match fields[..] {
+                [mode, score, lines, time, date, seed, name] => Score {
+                    mode: mode.to_string(),
+                    name: name.to_string(),
+                    score: parse_value(score).map_err(err)?,
+                    lines: parse_value(lines).map_err(err)?,
+                    time: Elapsed::from_millis(parse_value(time).map_err(err)?),
+                    date: parse_value(date).map_err(err)?,
+                    seed: parse_value(seed).map_err(err)?,
+                },
+                _ => return Err(err(format!("invalid score: {}", line))),
+            };
+            scores.push(score);
+        }
+
+        // Keep the order even if the file was edited by hand.
+        scores.sort_by_key(|s| std::cmp::Reverse(s.score));
+        Ok(ScoreTable { scores })
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value: {}", value))
+}
+
+// Formats seconds since the Unix epoch as a date in UTC.
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn format_date(secs: u64) -> String {
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(mode: &str, name: &str, score: usize) -> Score {
+        Score {
+            mode: mode.to_string(),
+            name: name.to_string(),
+            score,
+            lines: score / 100,
+            time: Elapsed::from_millis(61_500),
+            date: 1_792_281_600,
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn insert_scores() {
+        let mut table = ScoreTable::default();
+        assert_eq!(table.insert(score("a", "p1", 300)), Some(0));
+        assert_eq!(table.insert(score("a", "p2", 500)), Some(0));
+        assert_eq!(table.insert(score("b", "p3", 100)), Some(0));
+        assert_eq!(table.insert(score("a", "p4", 300)), Some(2));
+        assert_eq!(table.insert(score("a", "p5", 0)), None);
+
+        let names: Vec<_> = table.scores("a").map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["p2", "p1", "p4"]);
+        assert_eq!(table.modes(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn keep_top_scores_only() {
+        let mut table = ScoreTable::default();
+        for i in 1..=MAX_SCORES {
+            table.insert(score("a", "p", i * 100));
+        }
+        table.insert(score("b", "p", 50));
+
+        assert_eq!(table.rank("a", 100), None);
+        assert_eq!(table.rank("a", 150), Some(MAX_SCORES - 1));
+        assert_eq!(table.insert(score("a", "new", 150)), Some(MAX_SCORES - 1));
+
+        let scores: Vec<_> = table.scores("a").map(|s| s.score).collect();
+        assert_eq!(scores.len(), MAX_SCORES);
+        assert_eq!(scores.last(), Some(&150));
+        assert_eq!(table.scores("b").count(), 1);
+    }
+
+    #[test]
+    fn score_table_round_trip() {
+        let mut table = ScoreTable::default();
+        table.insert(score("bag7 guideline 16x16 level 1 lock 500", "ryym", 1200));
+        table.insert(score("random chaos 10x20 level 3 lock 500", "a b", 800));
+        let text = table.to_string();
+        assert_eq!(text.parse::<ScoreTable>().unwrap(), table);
+
+        let err = text
+            .replace("\t1200\t", "\tmany\t")
+            .parse::<ScoreTable>()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid value: many");
+    }
+
+    #[test]
+    fn date_format() {
+        struct Case {
+            secs: u64,
+            date: &'static str,
+        }
+        let cases = [
+            Case {
+                secs: 0,
+                date: "1970-01-01",
+            },
+            Case {
+                secs: 951_825_600,
+                date: "2000-02-29",
+            },
+            Case {
+                secs: 1_792_281_600,
+                date: "2026-10-18",
+            },
+        ];
+        for Case { secs, date } in cases.iter() {
+            assert_eq!(format_date(*secs), *date);
+        }
+    }
+}
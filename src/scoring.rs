@@ -0,0 +1,465 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+// LineClear describes what a lock of a tetromino achieved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineClear {
+    pub lines: usize,
+    pub spin: Spin,
+}
+
+impl LineClear {
+    pub fn new(lines: usize, spin: Spin) -> Self {
+        LineClear { lines, spin }
+    }
+
+    // Whether the clear is worth telling the player.
+    pub fn is_notable(self) -> bool {
+        self.lines > 0 || self.spin != Spin::None
+    }
+
+    // Difficult clears chain into back-to-back bonuses.
+    pub fn is_difficult(self) -> bool {
+        self.lines >= 4 || (self.lines > 0 && self.spin != Spin::None)
+    }
+}
+
+impl fmt::Display for LineClear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines = match self.lines {
+            0 => "",
+            1 => "SINGLE",
+            2 => "DOUBLE",
+            3 => "TRIPLE",
+            _ => "TETRIS",
+        };
+        let spin = match self.spin {
+            Spin::None => "",
+            Spin::Mini => "T-SPIN MINI",
+            Spin::Full => "T-SPIN",
+        };
+        match (spin, lines) {
+            ("", lines) => write!(f, "{}", lines),
+            (spin, "") => write!(f, "{}", spin),
+            (spin, lines) => write!(f, "{} {}", spin, lines),
+        }
+    }
+}
+
+// Scoring follows the Tetris guideline: multi-line clears are rewarded
+// more than the same lines cleared one by one, and the level multiplies them.
+// Consecutive line clears make a combo, and consecutive difficult clears
+// make a back-to-back chain.
+#[derive(Debug, Default)]
+pub struct Scoring {
+    score: usize,
+    combo: Option<usize>,
+    back_to_back: bool,
+}
+
+const SOFT_DROP_PER_CELL: usize = 1;
+const HARD_DROP_PER_CELL: usize = 2;
+const COMBO_BONUS: usize = 50;
+
+fn line_clear_points(clear: LineClear) -> usize {
+    match (clear.spin, clear.lines) {
+        (Spin::None, 0) => 0,
+        (Spin::None, 1) => 100,
+        (Spin::None, 2) => 300,
+        (Spin::None, 3) => 500,
+        (Spin::None, _) => 800,
+        (Spin::Mini, 0) => 100,
+        (Spin::Mini, 1) => 200,
+        (Spin::Mini, _) => 400,
+        (Spin::Full, 0) => 400,
+        (Spin::Full, 1) => 800,
+        (Spin::Full, 2) => 1200,
+        (Spin::Full, _) => 1600,
+    }
+}
+
+fn perfect_clear_points(lines: usize) -> usize {
+    match lines {
+        0 => 0,
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        _ => 2000,
+    }
+}
+
+impl Scoring {
+    pub fn new() -> Self {
+        Scoring::default()
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    // The number of line clears in a row after the first one.
+    pub fn combo(&self) -> usize {
+        self.combo.unwrap_or(0)
+    }
+
+    // Whether the next difficult clear gets the back-to-back bonus.
+    pub fn back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+
+    pub fn soft_drop(&mut self, cells: usize) {
+        self.score += cells * SOFT_DROP_PER_CELL;
+    }
+
+    pub fn hard_drop(&mut self, cells: usize) {
+        self.score += cells * HARD_DROP_PER_CELL;
+    }
+
+    // Perfect clears empty the whole field and get a bonus on top of the line clear.
+    pub fn perfect_clear(&mut self, lines: usize, level: u32) -> usize {
+        let points = perfect_clear_points(lines) * level as usize;
+        self.score += points;
+        points
+    }
+
+    // Returns the points awarded for the lines cleared by a lock.
+    pub fn line_clear(&mut self, clear: LineClear, level: u32) -> usize {
+        let level = level as usize;
+        let mut points = line_clear_points(clear) * level;
+
+        // Locks without lines break the combo, but not the back-to-back chain.
+        if clear.lines == 0 {
+            self.combo = None;
+            self.score += points;
+            return points;
+        }
+
+        if clear.is_difficult() {
+            if self.back_to_back {
+                points = points * 3 / 2;
+            }
+            self.back_to_back = true;
+        } else {
+            self.back_to_back = false;
+        }
+
+        let combo = self.combo.map_or(0, |c| c + 1);
+        self.combo = Some(combo);
+        points += COMBO_BONUS * combo * level;
+
+        self.score += points;
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_clear_score() {
+        struct Case<'a> {
+            title: &'a str,
+            clear: LineClear,
+            level: u32,
+            score: usize,
+        }
+        let cases = [
+            Case {
+                title: "no lines",
+                clear: LineClear::new(0, Spin::None),
+                level: 1,
+                score: 0,
+            },
+            Case {
+                title: "single",
+                clear: LineClear::new(1, Spin::None),
+                level: 1,
+                score: 100,
+            },
+            Case {
+                title: "double",
+                clear: LineClear::new(2, Spin::None),
+                level: 1,
+                score: 300,
+            },
+            Case {
+                title: "triple",
+                clear: LineClear::new(3, Spin::None),
+                level: 1,
+                score: 500,
+            },
+            Case {
+                title: "tetris",
+                clear: LineClear::new(4, Spin::None),
+                level: 1,
+                score: 800,
+            },
+            Case {
+                title: "tetris at level 5",
+                clear: LineClear::new(4, Spin::None),
+                level: 5,
+                score: 4000,
+            },
+            Case {
+                title: "double at level 3",
+                clear: LineClear::new(2, Spin::None),
+                level: 3,
+                score: 900,
+            },
+            Case {
+                title: "t-spin mini zero",
+                clear: LineClear::new(0, Spin::Mini),
+                level: 1,
+                score: 100,
+            },
+            Case {
+                title: "t-spin mini single",
+                clear: LineClear::new(1, Spin::Mini),
+                level: 1,
+                score: 200,
+            },
+            Case {
+                title: "t-spin mini double",
+                clear: LineClear::new(2, Spin::Mini),
+                level: 1,
+                score: 400,
+            },
+            Case {
+                title: "t-spin zero",
+                clear: LineClear::new(0, Spin::Full),
+                level: 1,
+                score: 400,
+            },
+            Case {
+                title: "t-spin single",
+                clear: LineClear::new(1, Spin::Full),
+                level: 1,
+                score: 800,
+            },
+            Case {
+                title: "t-spin double",
+                clear: LineClear::new(2, Spin::Full),
+                level: 1,
+                score: 1200,
+            },
+            Case {
+                title: "t-spin triple",
+                clear: LineClear::new(3, Spin::Full),
+                level: 1,
+                score: 1600,
+            },
+            Case {
+                title: "t-spin double at level 2",
+                clear: LineClear::new(2, Spin::Full),
+                level: 2,
+                score: 2400,
+            },
+        ];
+
+        for Case {
+            title,
+            clear,
+            level,
+            score,
+        } in cases.iter()
+        {
+            let mut s = Scoring::new();
+            assert_eq!(s.line_clear(*clear, *level), *score, "{}", title);
+            assert_eq!(s.score(), *score, "{}", title);
+        }
+    }
+
+    #[test]
+    fn combo_and_back_to_back() {
+        struct Case<'a> {
+            title: &'a str,
+            clears: &'a [LineClear],
+            score: usize,
+            combo: usize,
+            back_to_back: bool,
+        }
+        let single = LineClear::new(1, Spin::None);
+        let tetris = LineClear::new(4, Spin::None);
+        let nothing = LineClear::new(0, Spin::None);
+        let cases = [
+            Case {
+                title: "combo of singles",
+                clears: &[single, single, single],
+                score: 100 + 150 + 200,
+                combo: 2,
+                back_to_back: false,
+            },
+            Case {
+                title: "combo broken",
+                clears: &[single, nothing, single],
+                score: 100 + 100,
+                combo: 0,
+                back_to_back: false,
+            },
+            Case {
+                title: "back-to-back tetrises",
+                clears: &[tetris, tetris],
+                score: 800 + 1200 + 50,
+                combo: 1,
+                back_to_back: true,
+            },
+            Case {
+                title: "back-to-back broken by single",
+                clears: &[tetris, single, tetris],
+                score: 800 + 150 + 900,
+                combo: 2,
+                back_to_back: true,
+            },
+            Case {
+                title: "t-spin zero keeps back-to-back",
+                clears: &[tetris, LineClear::new(0, Spin::Full), tetris],
+                score: 800 + 400 + 1200,
+                combo: 0,
+                back_to_back: true,
+            },
+            Case {
+                title: "t-spin double after tetris",
+                clears: &[tetris, nothing, LineClear::new(2, Spin::Full)],
+                score: 800 + 1800,
+                combo: 0,
+                back_to_back: true,
+            },
+        ];
+
+        for Case {
+            title,
+            clears,
+            score,
+            combo,
+            back_to_back,
+        } in cases.iter()
+        {
+            let mut s = Scoring::new();
+            for &clear in clears.iter() {
+                s.line_clear(clear, 1);
+            }
+            assert_eq!(s.score(), *score, "{}", title);
+            assert_eq!(s.combo(), *combo, "{}", title);
+            assert_eq!(s.back_to_back(), *back_to_back, "{}", title);
+        }
+    }
+
+    #[test]
+    fn perfect_clear_score() {
+        struct Case {
+            lines: usize,
+            level: u32,
+            score: usize,
+        }
+        let cases = [
+            Case {
+                lines: 1,
+                level: 1,
+                score: 800,
+            },
+            Case {
+                lines: 2,
+                level: 1,
+                score: 1200,
+            },
+            Case {
+                lines: 3,
+                level: 2,
+                score: 3600,
+            },
+            Case {
+                lines: 4,
+                level: 3,
+                score: 6000,
+            },
+        ];
+
+        for (
+            i,
+            Case {
+                lines,
+                level,
+                score,
+            },
+        ) in cases.iter().enumerate()
+        {
+            let mut s = Scoring::new();
+            assert_eq!(s.perfect_clear(*lines, *level), *score, "test[{}]", i);
+        }
+    }
+
+    #[test]
+    fn line_clear_display() {
+        struct Case {
+            clear: LineClear,
+            text: &'static str,
+        }
+        let cases = [
+            Case {
+                clear: LineClear::new(4, Spin::None),
+                text: "TETRIS",
+            },
+            Case {
+                clear: LineClear::new(0, Spin::Full),
+                text: "T-SPIN",
+            },
+            Case {
+                clear: LineClear::new(1, Spin::Mini),
+                text: "T-SPIN MINI SINGLE",
+            },
+        ];
+        for Case { clear, text } in cases.iter() {
+            assert_eq!(clear.to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn drop_score() {
+        struct Case<'a> {
+            title: &'a str,
+            soft: usize,
+            hard: usize,
+            score: usize,
+        }
+        let cases = [
+            Case {
+                title: "soft drop",
+                soft: 5,
+                hard: 0,
+                score: 5,
+            },
+            Case {
+                title: "hard drop",
+                soft: 0,
+                hard: 12,
+                score: 24,
+            },
+            Case {
+                title: "both",
+                soft: 3,
+                hard: 7,
+                score: 17,
+            },
+        ];
+
+        for Case {
+            title,
+            soft,
+            hard,
+            score,
+        } in cases.iter()
+        {
+            let mut s = Scoring::new();
+            s.soft_drop(*soft);
+            s.hard_drop(*hard);
+            assert_eq!(s.score(), *score, "{}", title);
+        }
+    }
+}
@@ -1,16 +1,23 @@
 use crate::action::Action;
 use crate::color::Color;
-use crate::coord::Dir;
-use crate::inputs::{Inputs, Order};
+use crate::coord::{Coord, Dir};
+use crate::inputs::{Inputs, KeyAction, Order};
 use crate::play::Play;
-use failure::{Fail, Fallible};
+use crate::rules::Rules;
+use crate::scores;
+use crate::theme::Theme;
+use failure::{Error, Fail, Fallible};
 use std::io::Write;
 use std::iter;
+use std::mem;
+use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 use termion as tm;
 use termion::color;
 use termion::cursor::Goto;
+use termion::event::{Event, Key};
+use termion::style;
 
 pub struct Modal {
     pub title: String,
@@ -21,17 +28,123 @@ pub struct Modal {
 const TITLE: &str = "- T E X T R I S -";
 const FIELD_X: usize = 1;
 const FIELD_Y: usize = 3;
+const SIDE_MENU_WIDTH: usize = 20;
+const SIDE_MENU_HEIGHT: usize = 12;
+
+// Each preview piece takes up to 4x2 cells and a blank row below.
+const PREVIEW_WIDTH: usize = 10;
+const PREVIEW_PIECE_HEIGHT: usize = 3;
+const MODAL_WIDTH: usize = 39;
+// Content lines of modals are drawn inside the borders with a margin.
+pub const MODAL_LINE_WIDTH: usize = MODAL_WIDTH - 4;
+const MAX_ERROR_LINES: usize = 3;
+// The game over modal with errors of both the score and the replay is the
+// tallest modal other than the help, whose height depends on the keys.
+const RESULT_MODAL_LINES: usize = 14;
+const MODAL_X: usize = 3;
+const MODAL_Y: u16 = 5;
+
+// Returns the rows the modal takes with the content lines.
+// Borders, the title and the actions take 6 rows around the content.
+fn modal_rows(lines: usize) -> usize {
+    MODAL_Y as usize - 1 + lines + 6
+}
+
+// Returns the columns and rows needed to render the field, the side menu and modals.
+fn required_size(rules: &Rules, help_lines: usize) -> (usize, usize) {
+    let cols = (rules.width * 2 + 3 + PREVIEW_WIDTH + SIDE_MENU_WIDTH).max(MODAL_X + MODAL_WIDTH);
+    let preview_height = 1 + rules.preview * PREVIEW_PIECE_HEIGHT;
+    let rows = (FIELD_Y + rules.height.max(SIDE_MENU_HEIGHT).max(preview_height))
+        .max(modal_rows(RESULT_MODAL_LINES.max(help_lines)));
+    (cols, rows)
+}
+
+// Checks the terminal is large enough to render the game and the help
+// of the given lines.
+pub fn check_terminal_size(rules: &Rules, help_lines: usize) -> Fallible<()> {
+    let (cols, rows) = required_size(rules, help_lines);
+    let (term_cols, term_rows) =
+        tm::terminal_size().map_err(|e| e.context("failed to get terminal size"))?;
+    if (term_cols as usize) < cols || (term_rows as usize) < rows {
+        let target = if rows > required_size(rules, 0).1 {
+            format!("the help of {} lines", help_lines)
+        } else {
+            format!("a {}x{} field", rules.width, rules.height)
+        };
+        return Err(format_err!(
+            "terminal is too small for {} (needs {}x{}, got {}x{})",
+            target,
+            cols,
+            rows,
+            term_cols,
+            term_rows
+        ));
+    }
+    Ok(())
+}
+
+fn play_stats(play: &Play) -> Vec<String> {
+    vec![
+        format!("Time:  {}", play.elapsed()),
+        format!("Score: {}", play.score()),
+        format!("Level: {}", play.level()),
+        format!("Lines: {}", play.lines()),
+        format!("Perfect clears: {}", play.perfect_clears()),
+        format!("Seed:  {}", play.seed()),
+    ]
+}
+
+// Joins the words by spaces into lines, indenting the following lines.
+// Words too long for a line are split.
+pub fn wrap_words(words: &[String], width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in words.iter() {
+        let mut rest: Vec<char> = word.chars().collect();
+        if !line.trim_start().is_empty() {
+            if line.chars().count() + 1 + rest.len() > width {
+                lines.push(mem::replace(&mut line, "  ".to_string()));
+            } else {
+                line.push(' ');
+            }
+        }
+        while line.chars().count() + rest.len() > width {
+            let n = width - line.chars().count();
+            line.extend(rest.drain(..n));
+            lines.push(mem::replace(&mut line, "  ".to_string()));
+        }
+        line.extend(rest);
+    }
+    lines.push(line);
+    lines
+}
+
+// Formats the error with its causes to fit in a modal.
+fn error_lines(err: &Error) -> Vec<String> {
+    let text = err
+        .iter_chain()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(": ");
+    let words: Vec<_> = text.split_whitespace().map(str::to_string).collect();
+    wrap_words(&words, MODAL_LINE_WIDTH)
+        .into_iter()
+        .take(MAX_ERROR_LINES)
+        .collect()
+}
 
 pub struct Screen<W: Write> {
     stdout: W,
-    field_bg: Color,
+    theme: Theme,
+    show_ghost: bool,
 }
 
 impl<W: Write> Screen<W> {
-    pub fn new(stdout: W) -> Screen<W> {
+    pub fn new(stdout: W, show_ghost: bool, theme: Theme) -> Screen<W> {
         Screen {
             stdout,
-            field_bg: Color::black(),
+            theme,
+            show_ghost,
         }
     }
 
@@ -68,19 +181,50 @@ impl<W: Write> Screen<W> {
     }
 
     pub fn render(&mut self, play: &Play) -> Fallible<()> {
-        let field = play.field();
+        self.render_play(play, false)
+    }
+
+    // Paused games hide the blocks and the next tetrominos so they cannot be planned.
+    pub fn render_paused(&mut self, play: &Play) -> Fallible<()> {
+        self.render_play(play, true)
+    }
 
-        for (i, line) in field.lines_iter().enumerate() {
+    fn render_play(&mut self, play: &Play, paused: bool) -> Fallible<()> {
+        let field = play.field();
+        let falling = if paused { None } else { play.falling_tetro() };
+        let ghost = if self.show_ghost {
+            play.ghost_coords()
+        } else {
+            None
+        };
+
+        for (i, line) in field.visible_lines_iter().enumerate() {
             write!(
                 self.stdout,
                 "{}|{}",
                 Goto(FIELD_X as u16, (i + FIELD_Y) as u16),
-                color::Bg(self.field_bg),
+                color::Bg(self.theme.field_bg),
             )?;
-            for cell in line.iter() {
-                match cell {
+            for (j, cell) in line.iter().enumerate() {
+                let pos = Coord(j as i8, i as i8);
+                let cell = if paused { None } else { *cell };
+                let theme = &self.theme;
+                match cell.map(|b| theme.paint(b)) {
                     Some(block) => write!(self.stdout, "{} ", block),
-                    None => write!(self.stdout, "  "),
+                    None => match falling.map(|(b, c)| (theme.paint(b), c)) {
+                        Some((block, coords)) if coords.contains(&pos) => {
+                            write!(self.stdout, "{} ", block)
+                        }
+                        Some((block, _)) if ghost.is_some_and(|c| c.contains(&pos)) => write!(
+                            self.stdout,
+                            "{}{}.{}{} ",
+                            style::Faint,
+                            color::Fg(block.color),
+                            color::Fg(color::Reset),
+                            style::NoFaint,
+                        ),
+                        _ => write!(self.stdout, "  "),
+                    },
                 }?;
             }
             write!(self.stdout, "{}|", color::Bg(color::Reset))?;
@@ -93,49 +237,258 @@ impl<W: Write> Screen<W> {
         )?;
 
         let width = field.width();
-        for floor in iter::repeat("==").take(width + 1) {
+        for floor in iter::repeat_n("==", width + 1) {
             write!(self.stdout, "{}", floor)?;
         }
 
-        self.render_side_menu(play, (field.width() * 2 + 4) as u16)
+        if paused {
+            self.render_banner(play, "PAUSED", Color::white())?;
+        } else if play.is_perfect_clear_shown() {
+            self.render_banner(play, "PERFECT CLEAR", Color::yellow())?;
+        }
+
+        let preview_x = field.width() * 2 + 4;
+        self.render_preview(play, preview_x as u16, paused)
+            .map_err(|e| e.context("failed to render next tetrominos"))?;
+        self.render_side_menu(play, (preview_x + PREVIEW_WIDTH) as u16)
             .map_err(|e| e.context("failed to render side menu"))?;
 
         Ok(())
     }
 
+    // Banners are shown at the middle of the field.
+    fn render_banner(&mut self, play: &Play, banner: &str, fg: Color) -> Fallible<()> {
+        let field = play.field();
+        let x = (FIELD_X + 1 + (field.width() * 2).saturating_sub(banner.len()) / 2) as u16;
+        write!(
+            self.stdout,
+            "{}{}{}{}{}{}",
+            Goto(x, (FIELD_Y + field.height() / 2) as u16),
+            style::Bold,
+            color::Fg(fg),
+            banner,
+            color::Fg(color::Reset),
+            style::Reset,
+        )?;
+        Ok(())
+    }
+
+    fn render_preview(&mut self, play: &Play, x: u16, hidden: bool) -> Fallible<()> {
+        let y = FIELD_Y as u16;
+        write!(self.stdout, "{}Next:", Goto(x, y))?;
+
+        let blank = " ".repeat(PREVIEW_WIDTH);
+        for (i, (block, coords)) in play.next_tetro_hints().into_iter().enumerate() {
+            let top = y + 1 + (i * PREVIEW_PIECE_HEIGHT) as u16;
+            for dy in 0..2 {
+                write!(self.stdout, "{}{}", Goto(x, top + dy), blank)?;
+            }
+            if hidden {
+                continue;
+            }
+            for Coord(cx, cy) in coords.iter() {
+                write!(
+                    self.stdout,
+                    "{}{} ",
+                    Goto(x + *cx as u16 * 2, top + *cy as u16),
+                    self.theme.paint(block)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     fn render_side_menu(&mut self, play: &Play, x: u16) -> Fallible<()> {
         let y = FIELD_Y as u16;
 
-        let next_block = play.next_tetro_hint();
-        write!(self.stdout, "{}Next: {}", Goto(x, y), next_block)?;
+        match play.held_tetro_hint() {
+            Some(held_block) => write!(
+                self.stdout,
+                "{}Hold: {}",
+                Goto(x, y),
+                self.theme.paint(held_block)
+            ),
+            None => write!(self.stdout, "{}Hold: -", Goto(x, y)),
+        }?;
         write!(self.stdout, "{}?: Help", Goto(x, y + 2))?;
         write!(self.stdout, "{}Time:  {}", Goto(x, y + 4), play.elapsed())?;
         write!(self.stdout, "{}Score: {}", Goto(x, y + 5), play.score())?;
+        write!(self.stdout, "{}Level: {}", Goto(x, y + 6), play.level())?;
+        write!(self.stdout, "{}Lines: {}", Goto(x, y + 7), play.lines())?;
+
+        let combo = format!("Combo: {}", play.combo());
+        let b2b = if play.back_to_back() { "B2B" } else { "" };
+        for (dy, line) in [combo.as_str(), b2b].iter().enumerate() {
+            write!(
+                self.stdout,
+                "{}{:<width$}",
+                Goto(x, y + 8 + dy as u16),
+                line,
+                width = SIDE_MENU_WIDTH
+            )?;
+        }
+
+        let notice = play
+            .clear_notice()
+            .map_or(String::new(), |clear| clear.to_string());
+        write!(
+            self.stdout,
+            "{}{:<width$}",
+            Goto(x, y + 11),
+            notice,
+            width = SIDE_MENU_WIDTH
+        )?;
         Ok(())
     }
 
-    pub fn render_game_over(&mut self, inputs: &mut Inputs, play: &Play) -> Fallible<Action> {
+    pub fn render_game_over(
+        &mut self,
+        inputs: &mut Inputs,
+        play: &Play,
+        replay: &Fallible<PathBuf>,
+        rank: &Fallible<Option<usize>>,
+    ) -> Fallible<Action> {
+        let mut content = play_stats(play);
+        match rank {
+            Ok(Some(rank)) => content.push(format!("Rank:  #{}", rank + 1)),
+            Ok(None) => {}
+            Err(err) => {
+                content.push("High score: not saved".to_string());
+                content.extend(error_lines(err));
+            }
+        }
+        match replay {
+            Ok(path) => content.push(format!(
+                "Replay: {}",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            )),
+            Err(err) => {
+                content.push("Replay: not saved".to_string());
+                content.extend(error_lines(err));
+            }
+        }
         self.show_modal(
             inputs,
             &Modal {
                 title: "GAME OVER".to_string(),
-                content: vec![
-                    format!("Time:  {}", play.elapsed()),
-                    format!("Score: {}", play.score()),
-                ],
+                content,
                 actions: vec![Action::Retry, Action::Quit],
             },
         )
     }
 
+    pub fn render_replay_end(&mut self, inputs: &mut Inputs, play: &Play) -> Fallible<Action> {
+        self.show_modal(
+            inputs,
+            &Modal {
+                title: "REPLAY END".to_string(),
+                content: play_stats(play),
+                actions: vec![Action::Retry, Action::Quit],
+            },
+        )
+    }
+
+    // The status is shown between the title and the field.
+    pub fn render_replay_status(&mut self, speed: f64, paused: bool) -> Fallible<()> {
+        let status = format!("REPLAY {}x {}", speed, if paused { "PAUSED" } else { "" });
+        write!(
+            self.stdout,
+            "{}{:<width$}",
+            Goto(1, 2),
+            status,
+            width = SIDE_MENU_WIDTH
+        )?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
     pub fn show_modal(&mut self, inputs: &mut Inputs, modal: &Modal) -> Fallible<Action> {
-        let border = "---------------------------------------";
+        let actions = &modal.actions;
+        let mut select = 0;
+        let (y_actions, y_end) = self.draw_modal(modal, select)?;
+
+        loop {
+            if let Ok(order) = inputs.recv_order()? {
+                match order {
+                    Order::Move(Dir::Left) => {
+                        select = select.saturating_sub(1);
+                    }
+                    Order::Move(Dir::Right) if select < actions.len() - 1 => {
+                        select += 1;
+                    }
+                    Order::Select | Order::Quit => break,
+                    _ => {}
+                }
+            }
+
+            let action_btns = self.write_inline_actions(actions, select);
+            write!(
+                self.stdout,
+                "{}{}",
+                Goto(MODAL_X as u16 + 1, y_actions),
+                action_btns
+            )?;
+            self.stdout.flush()?;
+        }
+
+        self.clear_modal(y_end)?;
+        Ok(actions[select])
+    }
+
+    // Asks the name of the player for a new high score.
+    pub fn render_name_entry(
+        &mut self,
+        inputs: &mut Inputs,
+        play: &Play,
+        rank: usize,
+        default_name: &str,
+    ) -> Fallible<String> {
+        // Keys pressed while playing, like a hard drop, are not typed.
+        while inputs.try_recv_event()?.is_some() {}
+
+        let mut name = default_name.to_string();
+        let y_end = loop {
+            let mut content = play_stats(play);
+            content.push(format!("Rank:  #{}", rank + 1));
+            content.push(format!("Name:  {}_ ", name));
+            let modal = Modal {
+                title: "NEW HIGH SCORE".to_string(),
+                content,
+                actions: vec![Action::Ok],
+            };
+            let (_, y_end) = self.draw_modal(&modal, 0)?;
+
+            let input = inputs.recv_event()??;
+            if input.action == KeyAction::Release {
+                continue;
+            }
+            match input.event {
+                Event::Key(Key::Char('\n')) if !name.trim().is_empty() => break y_end,
+                Event::Key(Key::Backspace) => {
+                    name.pop();
+                }
+                Event::Key(Key::Char(c))
+                    if scores::is_name_char(c) && name.len() < scores::MAX_NAME_LEN =>
+                {
+                    name.push(c);
+                }
+                _ => {}
+            }
+        };
+
+        self.clear_modal(y_end)?;
+        Ok(name.trim().to_string())
+    }
+
+    // Draws a modal with the selected action and returns the rows
+    // of the actions and of the bottom border.
+    fn draw_modal(&mut self, modal: &Modal, select: usize) -> Fallible<(u16, u16)> {
+        let border = "-".repeat(MODAL_WIDTH);
         let inner_border = format!("|{}|", &border[1..border.len() - 1]);
         let back = " ".repeat(border.len());
         let inner_back = format!("|{}|", &back[1..back.len() - 1]);
-        let y_start = 5;
-        let mut y = y_start;
-        let x = 3;
+        let mut y = MODAL_Y;
+        let x = MODAL_X as u16;
 
         write!(self.stdout, "{}{}", Goto(x, y), border)?;
         y += 1;
@@ -147,6 +500,7 @@ impl<W: Write> Screen<W> {
 
         for line in modal.content.iter() {
             write!(self.stdout, "{}{}", Goto(x, y), inner_back)?;
+            let line: String = line.chars().take(MODAL_LINE_WIDTH).collect();
             write!(self.stdout, "{}{}", Goto(x + 2, y), line)?;
             y += 1;
         }
@@ -156,47 +510,21 @@ impl<W: Write> Screen<W> {
         write!(self.stdout, "{}{}", Goto(x, y), inner_back)?;
 
         let y_actions = y;
-
-        let actions = &modal.actions;
-        let mut select = 0;
-
-        let action_btns = self.write_inline_actions(actions, select);
+        let action_btns = self.write_inline_actions(&modal.actions, select);
         write!(self.stdout, "{}{}", Goto(x + 1, y_actions), action_btns)?;
         y += 1;
         write!(self.stdout, "{}{}", Goto(x, y), border)?;
 
         self.stdout.flush()?;
+        Ok((y_actions, y))
+    }
 
-        loop {
-            if let Ok(order) = inputs.recv_order()? {
-                match order {
-                    Order::Move(Dir::Left) => {
-                        if select > 0 {
-                            select -= 1;
-                        }
-                    }
-                    Order::Move(Dir::Right) => {
-                        if select < actions.len() - 1 {
-                            select += 1;
-                        }
-                    }
-                    Order::Select | Order::Quit => break,
-                    _ => {}
-                }
-            }
-
-            let action_btns = self.write_inline_actions(actions, select);
-            write!(self.stdout, "{}{}", Goto(x + 1, y_actions), action_btns)?;
-            self.stdout.flush()?;
-        }
-
-        // Clear modal
-        write!(self.stdout, "{}{}", Goto(x, y_start), back)?;
-        for y in y_start..=y {
-            write!(self.stdout, "{}{}", Goto(x, y), back)?;
+    fn clear_modal(&mut self, y_end: u16) -> Fallible<()> {
+        let back = " ".repeat(MODAL_WIDTH);
+        for y in MODAL_Y..=y_end {
+            write!(self.stdout, "{}{}", Goto(MODAL_X as u16, y), back)?;
         }
-
-        Ok(actions[select])
+        Ok(())
     }
 
     fn write_inline_actions(&self, actions: &[Action], select: usize) -> String {
@@ -219,3 +547,48 @@ impl<W: Write> Drop for Screen<W> {
         write!(self.stdout, "{}", tm::cursor::Show).expect("restore cursor");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_size_for_help() {
+        let rules = Rules {
+            height: 10,
+            ..Rules::default()
+        };
+        assert_eq!(required_size(&rules, 12), (65, 24));
+        assert_eq!(required_size(&rules, 16), (65, 26));
+    }
+
+    #[test]
+    fn wrap_lines() {
+        struct Case {
+            words: &'static [&'static str],
+            lines: &'static [&'static str],
+        }
+        let cases = [
+            Case {
+                words: &["←", "- Up"],
+                lines: &["← - Up"],
+            },
+            Case {
+                words: &["a", "b", "c", "d", "- Hi"],
+                lines: &["a b c", "  d", "  - Hi"],
+            },
+            Case {
+                words: &["ctrl-a", "- Q"],
+                lines: &["ctrl-a", "  - Q"],
+            },
+            Case {
+                words: &["failed", "/a/very/long/path"],
+                lines: &["failed", "  /a/v", "  ery/", "  long", "  /pat", "  h"],
+            },
+        ];
+        for Case { words, lines } in cases.iter() {
+            let words: Vec<_> = words.iter().map(|w| w.to_string()).collect();
+            assert_eq!(wrap_words(&words, 6), *lines, "{:?}", words);
+        }
+    }
+}
@@ -8,7 +8,129 @@ pub type Tetrominos = [Tetromino; N_TETROS];
 
 pub type TetroCoords = [Coord; 4];
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+type KickTable = [[Coord; 5]; 8];
+
+const NO_KICKS: [Coord; 1] = [Coord(0, 0)];
+
+const JLSTZ_KICKS: KickTable = [
+    [
+        Coord(0, 0),
+        Coord(-1, 0),
+        Coord(-1, -1),
+        Coord(0, 2),
+        Coord(-1, 2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(1, 0),
+        Coord(1, 1),
+        Coord(0, -2),
+        Coord(1, -2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(1, 0),
+        Coord(1, 1),
+        Coord(0, -2),
+        Coord(1, -2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(-1, 0),
+        Coord(-1, -1),
+        Coord(0, 2),
+        Coord(-1, 2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(1, 0),
+        Coord(1, -1),
+        Coord(0, 2),
+        Coord(1, 2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(-1, 0),
+        Coord(-1, 1),
+        Coord(0, -2),
+        Coord(-1, -2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(-1, 0),
+        Coord(-1, 1),
+        Coord(0, -2),
+        Coord(-1, -2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(1, 0),
+        Coord(1, -1),
+        Coord(0, 2),
+        Coord(1, 2),
+    ],
+];
+
+const I_KICKS: KickTable = [
+    [
+        Coord(0, 0),
+        Coord(-2, 0),
+        Coord(1, 0),
+        Coord(-2, 1),
+        Coord(1, -2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(2, 0),
+        Coord(-1, 0),
+        Coord(2, -1),
+        Coord(-1, 2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(-1, 0),
+        Coord(2, 0),
+        Coord(-1, -2),
+        Coord(2, 1),
+    ],
+    [
+        Coord(0, 0),
+        Coord(1, 0),
+        Coord(-2, 0),
+        Coord(1, 2),
+        Coord(-2, -1),
+    ],
+    [
+        Coord(0, 0),
+        Coord(2, 0),
+        Coord(-1, 0),
+        Coord(2, -1),
+        Coord(-1, 2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(-2, 0),
+        Coord(1, 0),
+        Coord(-2, 1),
+        Coord(1, -2),
+    ],
+    [
+        Coord(0, 0),
+        Coord(1, 0),
+        Coord(-2, 0),
+        Coord(1, 2),
+        Coord(-2, -1),
+    ],
+    [
+        Coord(0, 0),
+        Coord(-1, 0),
+        Coord(2, 0),
+        Coord(-1, -2),
+        Coord(2, 1),
+    ],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Tetromino {
     I,
     J,
@@ -35,52 +157,90 @@ impl Tetromino {
         ]
     }
 
+    // Preview coords put the spawn orientation at the top-left corner
+    // without the empty rows and columns of its bounding box.
+    pub fn preview_coords(self) -> TetroCoords {
+        let moves = self.make_moves(Dir::Up);
+        let left = moves.iter().map(|c| c.x()).min().unwrap();
+        let top = moves.iter().map(|c| c.y()).min().unwrap();
+        self.make_coords(Coord(-left, -top), Dir::Up)
+    }
+
+    // Shapes follow the Super Rotation System.
+    // Each move is relative to the top-left corner of the bounding box
+    // (4x4 for I, 3x3 for the others) so rotating keeps the standard centre.
     fn make_moves(self, dir: Dir) -> TetroCoords {
         use crate::coord::Dir::*;
 
         match self {
             Tetromino::I => match dir {
-                Up => [Coord(0, -2), Coord(0, -1), Coord(0, 0), Coord(0, 1)],
-                Right => [Coord(-1, 0), Coord(0, 0), Coord(1, 0), Coord(2, 0)],
-                Down => [Coord(0, -1), Coord(0, 0), Coord(0, 1), Coord(0, 2)],
-                Left => [Coord(1, 0), Coord(0, 0), Coord(-1, 0), Coord(-2, 0)],
+                Up => [Coord(0, 1), Coord(1, 1), Coord(2, 1), Coord(3, 1)],
+                Right => [Coord(2, 0), Coord(2, 1), Coord(2, 2), Coord(2, 3)],
+                Down => [Coord(0, 2), Coord(1, 2), Coord(2, 2), Coord(3, 2)],
+                Left => [Coord(1, 0), Coord(1, 1), Coord(1, 2), Coord(1, 3)],
             },
 
             Tetromino::J => match dir {
-                Up => [Coord(0, 0), Coord(0, 1), Coord(0, 2), Coord(-1, 2)],
-                Right => [Coord(0, 0), Coord(0, 1), Coord(1, 1), Coord(2, 1)],
-                Down => [Coord(0, 0), Coord(0, 1), Coord(0, 2), Coord(1, 0)],
-                Left => [Coord(-1, 0), Coord(0, 0), Coord(1, 0), Coord(1, 1)],
+                Up => [Coord(0, 0), Coord(0, 1), Coord(1, 1), Coord(2, 1)],
+                Right => [Coord(1, 0), Coord(2, 0), Coord(1, 1), Coord(1, 2)],
+                Down => [Coord(0, 1), Coord(1, 1), Coord(2, 1), Coord(2, 2)],
+                Left => [Coord(1, 0), Coord(1, 1), Coord(0, 2), Coord(1, 2)],
             },
 
             Tetromino::L => match dir {
-                Up => [Coord(0, 0), Coord(0, 1), Coord(0, 2), Coord(1, 2)],
-                Right => [Coord(0, 1), Coord(0, 0), Coord(1, 0), Coord(2, 0)],
-                Down => [Coord(0, 0), Coord(1, 0), Coord(1, 1), Coord(1, 2)],
-                Left => [Coord(-1, 1), Coord(0, 1), Coord(1, 1), Coord(1, 0)],
+                Up => [Coord(2, 0), Coord(0, 1), Coord(1, 1), Coord(2, 1)],
+                Right => [Coord(1, 0), Coord(1, 1), Coord(1, 2), Coord(2, 2)],
+                Down => [Coord(0, 1), Coord(1, 1), Coord(2, 1), Coord(0, 2)],
+                Left => [Coord(0, 0), Coord(1, 0), Coord(1, 1), Coord(1, 2)],
             },
 
-            Tetromino::O => [Coord(0, 0), Coord(0, 1), Coord(1, 0), Coord(1, 1)],
+            Tetromino::O => [Coord(1, 0), Coord(2, 0), Coord(1, 1), Coord(2, 1)],
 
             Tetromino::S => match dir {
-                Up | Down => [Coord(0, 0), Coord(1, 0), Coord(0, 1), Coord(-1, 1)],
-                Right | Left => [Coord(0, 0), Coord(0, 1), Coord(1, 1), Coord(1, 2)],
+                Up => [Coord(1, 0), Coord(2, 0), Coord(0, 1), Coord(1, 1)],
+                Right => [Coord(1, 0), Coord(1, 1), Coord(2, 1), Coord(2, 2)],
+                Down => [Coord(1, 1), Coord(2, 1), Coord(0, 2), Coord(1, 2)],
+                Left => [Coord(0, 0), Coord(0, 1), Coord(1, 1), Coord(1, 2)],
             },
 
             Tetromino::Z => match dir {
-                Up | Down => [Coord(0, 0), Coord(-1, 0), Coord(0, 1), Coord(1, 1)],
-                Right | Left => [Coord(0, 0), Coord(0, 1), Coord(-1, 1), Coord(-1, 2)],
+                Up => [Coord(0, 0), Coord(1, 0), Coord(1, 1), Coord(2, 1)],
+                Right => [Coord(2, 0), Coord(1, 1), Coord(2, 1), Coord(1, 2)],
+                Down => [Coord(0, 1), Coord(1, 1), Coord(1, 2), Coord(2, 2)],
+                Left => [Coord(1, 0), Coord(0, 1), Coord(1, 1), Coord(0, 2)],
             },
 
             Tetromino::T => match dir {
-                Up => [Coord(0, 0), Coord(0, 1), Coord(-1, 1), Coord(1, 1)],
-                Right => [Coord(0, 0), Coord(0, 1), Coord(1, 1), Coord(0, 2)],
-                Down => [Coord(0, 0), Coord(-1, 0), Coord(1, 0), Coord(0, 1)],
-                Left => [Coord(0, 0), Coord(0, 1), Coord(-1, 1), Coord(0, 2)],
+                Up => [Coord(1, 0), Coord(0, 1), Coord(1, 1), Coord(2, 1)],
+                Right => [Coord(1, 0), Coord(1, 1), Coord(2, 1), Coord(1, 2)],
+                Down => [Coord(0, 1), Coord(1, 1), Coord(2, 1), Coord(1, 2)],
+                Left => [Coord(1, 0), Coord(0, 1), Coord(1, 1), Coord(1, 2)],
             },
         }
     }
 
+    // Kicks returns the offsets to try in order when rotating from one state to another.
+    // The y axis of the SRS tables is flipped since our field grows downward.
+    pub fn kicks(self, from: Dir, to: Dir) -> &'static [Coord] {
+        let table = match self {
+            Tetromino::O => return &NO_KICKS,
+            Tetromino::I => &I_KICKS,
+            _ => &JLSTZ_KICKS,
+        };
+        let idx = match (from, to) {
+            (Dir::Up, Dir::Right) => 0,
+            (Dir::Right, Dir::Up) => 1,
+            (Dir::Right, Dir::Down) => 2,
+            (Dir::Down, Dir::Right) => 3,
+            (Dir::Down, Dir::Left) => 4,
+            (Dir::Left, Dir::Down) => 5,
+            (Dir::Left, Dir::Up) => 6,
+            (Dir::Up, Dir::Left) => 7,
+            _ => return &NO_KICKS,
+        };
+        &table[idx]
+    }
+
     pub fn default_char(self) -> char {
         use crate::tetromino::Tetromino::*;
         match self {
@@ -109,3 +269,47 @@ impl Tetromino {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kicks_are_symmetric() {
+        for &t in Tetromino::all().iter() {
+            for &from in Dir::all().iter() {
+                let to = from.next_dir();
+                let kicks = t.kicks(from, to);
+                let reversed = t.kicks(to, from);
+                assert_eq!(kicks.len(), reversed.len(), "{:?} {:?}", t, from);
+                for (k, r) in kicks.iter().zip(reversed.iter()) {
+                    assert_eq!(*k + *r, Coord(0, 0), "{:?} {:?} -> {:?}", t, from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_keeps_shape_in_box() {
+        for &t in Tetromino::all().iter() {
+            let size = if t == Tetromino::I { 4 } else { 3 };
+            for &dir in Dir::all().iter() {
+                let coords = t.make_coords(Coord(0, 0), dir);
+                for c in coords.iter() {
+                    assert!(0 <= c.x() && c.x() < size, "{:?} {:?}", t, dir);
+                    assert!(0 <= c.y() && c.y() < size, "{:?} {:?}", t, dir);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn preview_fits_in_two_rows() {
+        for &t in Tetromino::all().iter() {
+            let coords = t.preview_coords();
+            assert_eq!(coords.iter().map(|c| c.x()).min(), Some(0), "{:?}", t);
+            assert_eq!(coords.iter().map(|c| c.y()).min(), Some(0), "{:?}", t);
+            assert!(coords.iter().all(|c| c.x() < 4 && c.y() < 2), "{:?}", t);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::block::Block;
+use crate::color::Color;
+use crate::tetromino::{Tetromino, N_TETROS};
+
+// Theme decides the colors to render a game with.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub field_bg: Color,
+    // Colors of the tetrominos in the order of `Tetromino::all`.
+    pub pieces: [Color; N_TETROS],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let mut pieces = [Color::white(); N_TETROS];
+        for (i, t) in Tetromino::all().iter().enumerate() {
+            pieces[i] = t.default_block().color;
+        }
+        Theme {
+            field_bg: Color::black(),
+            pieces,
+        }
+    }
+}
+
+impl Theme {
+    pub fn from_name(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "mono" => Some(Theme {
+                field_bg: Color::black(),
+                pieces: [Color::white(); N_TETROS],
+            }),
+            _ => None,
+        }
+    }
+
+    // Sets the color of a tetromino named by its character.
+    pub fn set_piece_color(&mut self, chr: char, color: Color) -> bool {
+        match piece_index(chr) {
+            Some(i) => {
+                self.pieces[i] = color;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Blocks other than tetrominos' keep their own color.
+    pub fn paint(&self, block: Block) -> Block {
+        match piece_index(block.chr) {
+            Some(i) => Block::new(block.chr, self.pieces[i]),
+            None => block,
+        }
+    }
+}
+
+fn piece_index(chr: char) -> Option<usize> {
+    Tetromino::all()
+        .iter()
+        .position(|t| t.default_char() == chr.to_ascii_uppercase())
+}
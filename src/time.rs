@@ -0,0 +1,9 @@
+use std::time::Duration;
+
+// Plays advance by fixed frames, so times in rules are counted in frames.
+pub const FPS: u64 = 60;
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / FPS);
+
+pub fn frames(millis: u64) -> u64 {
+    millis * FPS / 1000
+}